@@ -0,0 +1,91 @@
+//! Compare the chunked kernels with the per-element closures `DFN!` builds.
+//!
+//! Run with `cargo bench --bench kernels [-- <elements>]`.
+use {
+    dezorr::{arrow::ArrowType, kernels::SimdFloat, DFN},
+    std::{hint::black_box, time::Instant},
+};
+
+fn measure(label: &str, mut f: impl FnMut()) {
+    f();
+    let mut rounds = 0;
+    let start = Instant::now();
+    while start.elapsed().as_millis() < 300 {
+        f();
+        rounds += 1;
+    }
+    let micros = start.elapsed().as_secs_f64() * 1e6 / rounds as f64;
+    println!("{label:<24} {micros:>10.1} us");
+}
+
+macro_rules! compare {
+    ($t: ty, $n: expr) => {{
+        let n = $n;
+        let xs = (0..n)
+            .map(|i| (i as $t / n as $t) * 8.0 - 4.0)
+            .collect::<Vec<$t>>();
+        let ys = xs.iter().rev().cloned().collect::<Vec<$t>>();
+        let mut out = vec![0.0 as $t; n];
+        let t = stringify!($t);
+        println!("{t}, {n} elements");
+        let naive: Option<ArrowType<$t>> = DFN!(|x: $t| x.exp());
+        let naive = naive.unwrap();
+        measure(&format!("exp {t} DFN"), || {
+            black_box(naive(black_box(&xs)));
+        });
+        measure(&format!("exp {t} kernel"), || {
+            <$t>::exp_slice(black_box(&xs), &mut out)
+        });
+        let naive: Option<ArrowType<$t>> = DFN!(|x: $t| x.tanh());
+        let naive = naive.unwrap();
+        measure(&format!("tanh {t} DFN"), || {
+            black_box(naive(black_box(&xs)));
+        });
+        measure(&format!("tanh {t} kernel"), || {
+            <$t>::tanh_slice(black_box(&xs), &mut out)
+        });
+        let naive: Option<ArrowType<$t>> = DFN!(|x: $t| 1.0 / (1.0 + (-x).exp()));
+        let naive = naive.unwrap();
+        measure(&format!("sigmoid {t} DFN"), || {
+            black_box(naive(black_box(&xs)));
+        });
+        measure(&format!("sigmoid {t} kernel"), || {
+            <$t>::sigmoid_slice(black_box(&xs), &mut out)
+        });
+        measure(&format!("mul {t} closure"), || {
+            black_box(
+                xs.iter()
+                    .zip(ys.iter())
+                    .map(|(a, b)| a.clone() * b.clone())
+                    .collect::<Vec<_>>(),
+            );
+        });
+        measure(&format!("mul {t} kernel"), || {
+            <$t>::mul_slice(black_box(&xs), black_box(&ys), &mut out)
+        });
+        // the DFN backward evaluates the local derivative, then scales it by the gradient
+        let naive: Option<ArrowType<$t>> = DFN!(|y: $t| 1.0 - y * y);
+        let naive = naive.unwrap();
+        measure(&format!("tanh' {t} DFN"), || {
+            let d = naive(black_box(&ys));
+            black_box(
+                d.into_iter()
+                    .zip(xs.iter())
+                    .map(|(d, g)| g.clone() * d)
+                    .collect::<Vec<_>>(),
+            );
+        });
+        measure(&format!("tanh' {t} kernel"), || {
+            <$t>::tanh_backward(black_box(&xs), black_box(&ys), &mut out)
+        });
+    }};
+}
+
+fn main() {
+    let n = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse::<usize>().ok())
+        .unwrap_or(1 << 16);
+    compare!(f32, n);
+    compare!(f64, n);
+}
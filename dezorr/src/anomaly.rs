@@ -0,0 +1,156 @@
+//! Detection of NaN and infinite values while a graph propagates.
+//!
+//! [`Function::try_propagate_forward`] and [`Function::try_propagate_backward`]
+//! check the outputs of every function they apply and stop at the first one
+//! that is not finite. While a [`detect_anomaly`] guard is alive on the
+//! current thread, the plain `propagate_forward` and `propagate_backward` do
+//! the same and panic with the report.
+//!
+//! [`Function::try_propagate_forward`]: crate::func::Function::try_propagate_forward
+//! [`Function::try_propagate_backward`]: crate::func::Function::try_propagate_backward
+use std::{cell::Cell, fmt};
+
+thread_local! {
+    static DETECTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// whether propagation on this thread checks for non-finite values
+pub fn is_detecting() -> bool {
+    DETECTING.with(|d| d.get())
+}
+
+/// turn detection on until the returned guard is dropped
+pub fn detect_anomaly() -> AnomalyMode {
+    AnomalyMode(DETECTING.with(|d| d.replace(true)))
+}
+
+/// restores the previous detection mode on drop
+pub struct AnomalyMode(bool);
+
+impl Drop for AnomalyMode {
+    fn drop(&mut self) {
+        DETECTING.with(|d| d.set(self.0));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Forward,
+    Backward,
+}
+
+/// the first function whose outputs were not finite
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    pub node: String,
+    pub pass: Pass,
+    /// the inputs of the function in the forward pass
+    pub inputs: Vec<String>,
+    /// the gradients it received, in the backward pass only
+    pub upstream: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pass = match self.pass {
+            Pass::Forward => "forward",
+            Pass::Backward => "backward",
+        };
+        write!(
+            f,
+            "non-finite output in the {pass} pass of {}: inputs [{}]",
+            self.node,
+            self.inputs.join(", ")
+        )?;
+        if self.pass == Pass::Backward {
+            write!(f, ", upstream gradients [{}]", self.upstream.join(", "))?;
+        }
+        write!(f, ", outputs [{}]", self.outputs.join(", "))
+    }
+}
+
+impl std::error::Error for Anomaly {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        func::{Function, FunctionOn},
+        DFN, TERMINAL, VARIABLE,
+    };
+    #[test]
+    fn test_forward_anomaly() {
+        // test_step_2_base4's graph with x = 0
+        let x: Function<f64> = VARIABLE!(1.0, 0.0);
+        let y: Function<f64> = TERMINAL!(1.0, 1.0);
+        let fa: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| 2.0 * x), DFN!(|_: f64| 2.0f64)).named("fa");
+        let fb: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| 1.0 / x), DFN!(|x: f64| -x.powi(-2))).named("fb");
+        x.link_to(&fa);
+        x.link_to(&fa);
+        fa.link_to(&fb);
+        fa.link_to(&fb);
+        fb.link_to(&y);
+        fb.link_to(&y);
+        let anomaly = x.try_propagate_forward().unwrap_err();
+        assert_eq!(anomaly.node, "fb");
+        assert_eq!(anomaly.pass, Pass::Forward);
+        assert_eq!(anomaly.inputs, vec!["2.0", "0.0"]);
+        assert_eq!(
+            anomaly.to_string(),
+            "non-finite output in the forward pass of fb: inputs [2.0, 0.0], outputs [0.5, inf]"
+        );
+        // propagation stopped before the terminal
+        assert!(!y.on_f(|a| a.is_applied()));
+    }
+    #[test]
+    fn test_backward_anomaly() {
+        let x: Function<f64> = VARIABLE!(0.0);
+        let y: Function<f64> = TERMINAL!(1.0);
+        let root: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| x.sqrt()), DFN!(|x: f64| 0.5 / x.sqrt()))
+                .named("sqrt");
+        x.followed_by(&root).followed_by(&y);
+        assert_eq!(x.try_propagate_forward(), Ok(()));
+        let anomaly = y.try_propagate_backward().unwrap_err();
+        assert_eq!(
+            anomaly.to_string(),
+            "non-finite output in the backward pass of sqrt: inputs [0.0], upstream gradients [1.0], outputs [inf]"
+        );
+    }
+    #[test]
+    fn test_unnamed_anomaly() {
+        // unnamed functions are told apart by their creation index
+        let x: Function<f64> = VARIABLE!(0.0, 0.0);
+        let f: Function<f64> = Function::<f64>::new(DFN!(|x: f64| x), DFN!(|_: f64| 1.0));
+        let g: Function<f64> = Function::<f64>::new(DFN!(|x: f64| x.ln()), DFN!(|x: f64| 1.0 / x));
+        let y: Function<f64> = TERMINAL!(1.0, 1.0);
+        x.followed_by(&f).followed_by(&y);
+        x.followed_by(&g).followed_by(&y);
+        assert_ne!(f.name(), g.name());
+        assert!(g.name().starts_with("function#"));
+        let anomaly = x.try_propagate_forward().unwrap_err();
+        assert_eq!(anomaly.node, g.name());
+    }
+    #[test]
+    #[should_panic(expected = "forward pass of reciprocal")]
+    fn test_detect_anomaly_mode() {
+        let x: Function<f64> = VARIABLE!(0.0);
+        let f: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| 1.0 / x), DFN!(|x: f64| -x.powi(-2)))
+                .named("reciprocal");
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&f).followed_by(&y);
+        {
+            let _outer = detect_anomaly();
+            drop(detect_anomaly());
+            assert!(is_detecting());
+        }
+        assert!(!is_detecting());
+        x.propagate_forward();
+        let _mode = detect_anomaly();
+        x.propagate_forward();
+    }
+}
@@ -23,9 +23,15 @@ macro_rules! TFN {
 
 pub type ArrowType<D> = Box<dyn Fn(&[D]) -> Vec<D>>;
 
+/// A backward function taking the forward inputs and the upstream gradients
+/// and returning the gradients of the inputs.
+pub type VjpType<D> = Box<dyn Fn(&[D], &[D]) -> Vec<D>>;
+
 #[derive(Clone)]
 struct ConnectionBody<'a, D: ContinuousDomain> {
     value: Option<D>,
+    /// whether the value arrived after the target last applied
+    fresh: bool,
     source: &'a Function<'a, D>,
     target: &'a Function<'a, D>,
 }
@@ -37,7 +43,7 @@ impl<'a, D: ContinuousDomain + std::fmt::Debug> std::fmt::Debug for Connection<'
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         let binding = self.0.borrow();
         f.debug_struct("Connection")
-            .field("vaule", &binding.value)
+            .field("value", &binding.value)
             .finish()
     }
 }
@@ -45,16 +51,33 @@ impl<'a, D: ContinuousDomain + std::fmt::Debug> std::fmt::Debug for Connection<'
 impl<'a, D: ContinuousDomain> Connection<'a, D> {
     pub fn new(value: Option<D>, source: &'a Function<'a, D>, target: &'a Function<'a, D>) -> Self {
         Connection(Rc::new(RefCell::new(ConnectionBody {
+            fresh: value.is_some(),
             value,
             source,
             target,
         })))
     }
+    /// an identity shared by the clones of this connection
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+    pub fn source(&self) -> &'a Function<'a, D> {
+        self.0.borrow().source
+    }
+    pub fn target(&self) -> &'a Function<'a, D> {
+        self.0.borrow().target
+    }
     pub fn get_value(&self) -> Option<D> {
         self.0.borrow().value.clone()
     }
     pub fn set_value(&self, val: Option<D>) {
-        self.0.borrow_mut().value = val;
+        let body = &mut self.0.borrow_mut();
+        body.fresh = val.is_some();
+        body.value = val;
+    }
+    /// whether the value came after the last use of it by the target
+    pub fn is_fresh(&self) -> bool {
+        self.0.borrow().fresh
     }
 }
 
@@ -63,6 +86,7 @@ impl<'a, D: ContinuousDomain> Connection<'a, D> {
 pub struct Arrow<'a, D: ContinuousDomain> {
     domain: Vec<Connection<'a, D>>,
     pub arrow: Option<Rc<ArrowType<D>>>,
+    pub vjp: Option<Rc<VjpType<D>>>,
     values: Vec<D>,
     codomain: Vec<Connection<'a, D>>,
 }
@@ -72,6 +96,7 @@ impl<D: ContinuousDomain> Clone for Arrow<'_, D> {
         Arrow {
             domain: Vec::new(),
             arrow: self.arrow.clone(),
+            vjp: self.vjp.clone(),
             values: self.values.clone(),
             codomain: Vec::new(),
         }
@@ -80,7 +105,7 @@ impl<D: ContinuousDomain> Clone for Arrow<'_, D> {
 
 impl<'a, D: ContinuousDomain + std::fmt::Debug> std::fmt::Debug for Arrow<'a, D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        f.debug_struct(if self.arrow.is_some() {
+        f.debug_struct(if self.arrow.is_some() || self.vjp.is_some() {
             "Arrow"
         } else {
             "Variable"
@@ -99,6 +124,12 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
             ..Arrow::default()
         }
     }
+    pub fn with_vjp(vjp: Option<VjpType<D>>) -> Self {
+        Arrow {
+            vjp: vjp.map(Rc::new),
+            ..Arrow::default()
+        }
+    }
     pub fn coterminal(values: Vec<D>) -> Self {
         Arrow {
             values,
@@ -109,10 +140,19 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
         Arrow::default()
     }
     fn is_terminal(&self) -> bool {
-        self.arrow.is_none() && self.codomain.is_empty()
+        self.arrow.is_none() && self.vjp.is_none() && self.codomain.is_empty()
     }
     pub fn is_coterminal(&self) -> bool {
-        self.arrow.is_none() && self.domain.is_empty() && !self.values.is_empty()
+        self.arrow.is_none()
+            && self.vjp.is_none()
+            && self.domain.is_empty()
+            && !self.values.is_empty()
+    }
+    pub fn domain(&self) -> &[Connection<'a, D>] {
+        &self.domain
+    }
+    pub fn codomain(&self) -> &[Connection<'a, D>] {
+        &self.codomain
     }
     pub fn add_input(&mut self, connection: Connection<'a, D>) {
         self.domain.push(connection);
@@ -120,6 +160,23 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
     pub fn add_output(&mut self, connection: Connection<'a, D>) {
         self.codomain.push(connection);
     }
+    /// put `connection` in place of the input lane at `position`
+    pub fn replace_input(&mut self, position: usize, connection: Connection<'a, D>) {
+        self.domain[position] = connection;
+    }
+    pub fn replace_output(&mut self, position: usize, connection: Connection<'a, D>) {
+        self.codomain[position] = connection;
+    }
+    pub fn remove_input(&mut self, position: usize) {
+        self.domain.remove(position);
+    }
+    /// drop the output lane at `position`, with its value for a coterminal
+    pub fn remove_output(&mut self, position: usize) {
+        if self.is_coterminal() {
+            self.values.remove(position);
+        }
+        self.codomain.remove(position);
+    }
     pub fn inputs(&self) -> Vec<Option<D>> {
         self.domain
             .iter()
@@ -129,8 +186,18 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
     pub fn outputs(&self) -> Vec<D> {
         self.values.to_vec()
     }
+    pub fn set_values(&mut self, values: Vec<D>) {
+        self.values = values;
+    }
+    /// whether every input lane got a value since the arrow last applied
     pub fn is_applicable(&self) -> bool {
-        self.domain.iter().all(|x| x.0.borrow().value.is_some())
+        self.domain.iter().all(|x| x.is_fresh())
+    }
+    /// mark the inputs as used, keeping them for the backward to read
+    fn consume(&self) {
+        for c in &self.domain {
+            c.0.borrow_mut().fresh = false;
+        }
     }
     pub fn is_applied(&self) -> bool {
         // self.is_applicable() && (self.domain.len() == self.values.len())
@@ -150,8 +217,9 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
                 .map(|c| c.0.borrow().value.as_ref().unwrap().clone())
                 .collect::<Vec<D>>();
             self.values = f(&data);
-        } else if !self.domain.is_empty() && self.values.is_empty() {
-            // terminal
+        } else if !self.domain.is_empty() {
+            // terminal, taking the latest inputs so that every pass replaces
+            // what an earlier one left
             self.values = self
                 .domain
                 .iter()
@@ -160,7 +228,16 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
         }
     }
     pub fn apply_b(&mut self, forward: &[&D]) {
-        if let Some(f) = &self.arrow {
+        if let Some(vjp) = &self.vjp {
+            assert!(self.is_applicable());
+            let data = forward.iter().map(|v| (*v).clone()).collect::<Vec<_>>();
+            let grads = self
+                .domain
+                .iter()
+                .map(|c| c.0.borrow().value.as_ref().unwrap().clone())
+                .collect::<Vec<D>>();
+            self.values = vjp(&data, &grads);
+        } else if let Some(f) = &self.arrow {
             // normal arrow
             assert!(self.is_applicable());
             // self.values = self
@@ -176,8 +253,8 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
                 .enumerate()
                 .map(|(i, x)| self.domain[i].0.borrow().value.as_ref().unwrap().clone() * x)
                 .collect::<Vec<_>>();
-        } else if !self.domain.is_empty() && self.values.is_empty() {
-            // terminal
+        } else if !self.domain.is_empty() {
+            // terminal, taking the latest inputs
             self.values = self
                 .domain
                 .iter()
@@ -185,14 +262,21 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
                 .collect::<Vec<_>>();
         }
     }
+    /// apply once every input lane holds a value not used yet, terminals
+    /// included: a function fed by several others waits for the last of them,
+    /// on every pass
     pub fn propagate_forward(&mut self) -> Option<Vec<&'a Function<'a, D>>> {
-        (self.is_coterminal()
-            || (self.is_terminal() && (!self.is_applied()) || self.is_applicable()))
-        .then(|| {
+        self.is_applicable().then(|| {
             self.apply_f();
-            assert!(self.is_terminal() || self.values.len() == self.codomain.len());
+            assert!(
+                self.is_terminal() || self.values.len() == self.codomain.len(),
+                "{} values for {} outgoing connections",
+                self.values.len(),
+                self.codomain.len()
+            );
+            self.consume();
             for (i, t) in self.codomain.iter().enumerate() {
-                t.0.borrow_mut().value = Some(self.values[i].clone());
+                t.set_value(Some(self.values[i].clone()));
             }
             self.codomain
                 .iter()
@@ -200,14 +284,20 @@ impl<'a, D: ContinuousDomain> Arrow<'a, D> {
                 .collect::<Vec<_>>()
         })
     }
+    /// apply once every gradient lane holds a value, like
+    /// [`Arrow::propagate_forward`]
     pub fn propagate_backward(&mut self, forward: &[&D]) -> Option<Vec<&'a Function<'a, D>>> {
-        (self.is_coterminal()
-            || (self.is_terminal() && (!self.is_applied()) || self.is_applicable()))
-        .then(|| {
+        self.is_applicable().then(|| {
             self.apply_b(forward);
-            assert!(self.is_terminal() || self.values.len() == self.codomain.len());
+            assert!(
+                self.is_terminal() || self.values.len() == self.codomain.len(),
+                "{} values for {} outgoing connections",
+                self.values.len(),
+                self.codomain.len()
+            );
+            self.consume();
             for (i, t) in self.codomain.iter().enumerate() {
-                t.0.borrow_mut().value = Some(self.values[i].clone());
+                t.set_value(Some(self.values[i].clone()));
             }
             self.codomain
                 .iter()
@@ -246,4 +336,55 @@ mod tests {
         a1.add_input(c0);
         // let _c = Connection::new(0.0f64, &c0);
     }
+    #[test]
+    fn test_terminal_waits_for_all_lanes() {
+        let a: Function<f64> = Function::coterminal(vec![1.0]);
+        let b: Function<f64> = Function::coterminal(vec![2.0]);
+        let y: Function<f64> = Function::terminal(vec![1.0, 1.0]);
+        a.link_to(&y);
+        b.link_to(&y);
+        a.propagate_forward();
+        assert!(!y.on_f(|f| f.is_applied()));
+        b.propagate_forward();
+        assert_eq!(y.on_f(|f| f.outputs()), vec![1.0, 2.0]);
+        // the variables wait for every gradient lane in the same way
+        y.propagate_backward();
+        assert_eq!(a.on_b(|f| f.outputs()), vec![1.0]);
+        // and so on every later pass, rather than mixing in the old values
+        a.assign(vec![5.0]);
+        a.propagate_forward();
+        assert_eq!(y.on_f(|f| f.outputs()), vec![1.0, 2.0]);
+        b.propagate_forward();
+        assert_eq!(y.on_f(|f| f.outputs()), vec![5.0, 2.0]);
+    }
+    #[test]
+    fn test_function_applies_once_per_pass() {
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counted = calls.clone();
+        let a: Function<f64> = Function::coterminal(vec![1.0]);
+        let b: Function<f64> = Function::coterminal(vec![2.0]);
+        let add: Function<f64> = Function::new(
+            TFN!(move |xs: &[f64]| {
+                counted.set(counted.get() + 1);
+                vec![xs[0] + xs[1]]
+            }),
+            TFN!(|_: &[f64]| vec![1.0, 1.0]),
+        );
+        let y: Function<f64> = Function::terminal(vec![1.0]);
+        a.link_to(&add);
+        b.link_to(&add);
+        add.link_to(&y);
+        for pass in 1..=2 {
+            a.propagate_forward();
+            b.propagate_forward();
+            assert_eq!(calls.get(), pass);
+        }
+        // one fresh input is not enough
+        b.assign(vec![4.0]);
+        b.propagate_forward();
+        assert_eq!(calls.get(), 2);
+        a.propagate_forward();
+        assert_eq!(y.on_f(|f| f.outputs()), vec![5.0]);
+        assert_eq!(calls.get(), 3);
+    }
 }
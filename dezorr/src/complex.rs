@@ -0,0 +1,253 @@
+//! Complex numbers and graph functions over them.
+//!
+//! Gradients follow the conjugate Wirtinger convention of PyTorch: the
+//! gradient of a real loss `L` at `z = x + iy` is `∂L/∂x + i ∂L/∂y`, which is
+//! `2 ∂L/∂z̄` and points in the direction of steepest ascent. A holomorphic
+//! `w = f(z)` then passes back `gw * conj(f'(z))`. Functions with a real
+//! result, like [`abs`], give a complex number with a zero imaginary part and
+//! use only the real part of their upstream gradient.
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        types::{ContinuousDomain, Float},
+        TFN,
+    },
+    std::fmt,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex<T: Float> {
+    pub re: T,
+    pub im: T,
+}
+
+impl<T: Float> Complex<T> {
+    pub fn new(re: T, im: T) -> Self {
+        Complex { re, im }
+    }
+    /// `x + 0i`
+    pub fn real(re: T) -> Self {
+        Complex::new(re, T::default())
+    }
+    pub fn i() -> Self {
+        Complex::new(T::default(), T::from_f64(1.0))
+    }
+    pub fn conj(&self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+    pub fn abs(&self) -> T {
+        self.re.hypot(self.im)
+    }
+    pub fn arg(&self) -> T {
+        self.im.atan2(self.re)
+    }
+    pub fn exp(&self) -> Self {
+        let r = self.re.exp();
+        Complex::new(r * self.im.cos(), r * self.im.sin())
+    }
+    pub fn scale(&self, k: T) -> Self {
+        Complex::new(self.re * k, self.im * k)
+    }
+}
+
+impl<T: Float> From<T> for Complex<T> {
+    fn from(re: T) -> Self {
+        Complex::real(re)
+    }
+}
+
+impl<T: Float> std::ops::Add for Complex<T> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl<T: Float> std::ops::Sub for Complex<T> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl<T: Float> std::ops::Mul for Complex<T> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl<T: Float> std::ops::Div for Complex<T> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        let d = other.re * other.re + other.im * other.im;
+        let n = self * other.conj();
+        Complex::new(n.re / d, n.im / d)
+    }
+}
+
+impl<T: Float> std::ops::Neg for Complex<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl<T: Float> fmt::Display for Complex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.im < T::default() { '-' } else { '+' };
+        let im = if self.im < T::default() {
+            -self.im
+        } else {
+            self.im
+        };
+        match f.precision() {
+            Some(p) => write!(f, "{:.p$}{sign}{:.p$}i", self.re, im),
+            None => write!(f, "{}{sign}{}i", self.re, im),
+        }
+    }
+}
+
+impl<T: Float> ContinuousDomain for Complex<T> {
+    fn is_finite(&self) -> bool {
+        self.re.to_f64().is_finite() && self.im.to_f64().is_finite()
+    }
+}
+
+/// a function applying `f` to every input, whose backward maps each input and
+/// its upstream gradient with `vjp`
+fn unary<'a, T: Float>(
+    f: fn(&Complex<T>) -> Complex<T>,
+    vjp: fn(&Complex<T>, &Complex<T>) -> Complex<T>,
+) -> Function<'a, Complex<T>> {
+    Function::with_vjp(
+        TFN!(move |zs: &[Complex<T>]| zs.iter().map(f).collect()),
+        TFN!(move |zs: &[Complex<T>], gws: &[Complex<T>]| zs
+            .iter()
+            .zip(gws.iter())
+            .map(|(z, gw)| vjp(z, gw))
+            .collect()),
+    )
+}
+
+pub fn exp<'a, T: Float>() -> Function<'a, Complex<T>> {
+    unary(|z| z.exp(), |z, gw| *gw * z.exp().conj()).named("exp")
+}
+
+pub fn conj<'a, T: Float>() -> Function<'a, Complex<T>> {
+    unary(|z| z.conj(), |_, gw| gw.conj()).named("conj")
+}
+
+/// `|z|`; the gradient at zero is zero
+pub fn abs<'a, T: Float>() -> Function<'a, Complex<T>> {
+    unary(
+        |z| Complex::real(z.abs()),
+        |z, gw| {
+            let r = z.abs();
+            if r == T::default() {
+                Complex::default()
+            } else {
+                z.scale(gw.re / r)
+            }
+        },
+    )
+    .named("abs")
+}
+
+pub fn real<'a, T: Float>() -> Function<'a, Complex<T>> {
+    unary(|z| Complex::real(z.re), |_, gw| Complex::real(gw.re)).named("real")
+}
+
+pub fn imag<'a, T: Float>() -> Function<'a, Complex<T>> {
+    unary(
+        |z| Complex::real(z.im),
+        |_, gw| Complex::new(T::default(), gw.re),
+    )
+    .named("imag")
+}
+
+/// the product of two inputs
+pub fn mul<'a, T: Float>() -> Function<'a, Complex<T>> {
+    Function::with_vjp(
+        TFN!(|zs: &[Complex<T>]| vec![zs[0] * zs[1]]),
+        TFN!(|zs: &[Complex<T>], gws: &[Complex<T>]| vec![
+            gws[0] * zs[1].conj(),
+            gws[0] * zs[0].conj()
+        ]),
+    )
+    .named("mul")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TERMINAL, VARIABLE};
+    #[test]
+    fn test_complex_arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        assert_eq!((a * b) / b, a);
+        assert_eq!(Complex::i() * Complex::i(), Complex::real(-1.0));
+        let e = Complex::new(0.0, std::f64::consts::PI).exp();
+        assert!((e - Complex::real(-1.0)).abs() < 1e-15);
+        assert_eq!(format!("{:.1}", b), "3.0-1.0i");
+    }
+    /// `∂L/∂x + i ∂L/∂y` by central differences
+    fn numerical_grad(loss: impl Fn(Complex<f64>) -> f64, z: Complex<f64>) -> Complex<f64> {
+        let h = 1e-6;
+        let dx = (loss(z + Complex::real(h)) - loss(z - Complex::real(h))) / (2.0 * h);
+        let dy = (loss(z + Complex::new(0.0, h)) - loss(z - Complex::new(0.0, h))) / (2.0 * h);
+        Complex::new(dx, dy)
+    }
+    #[test]
+    fn test_wirtinger_gradients() {
+        // L = |exp(z) * w|, a real loss of complex intermediates
+        let (z0, w0) = (Complex::new(0.3, -0.7), Complex::new(1.5, 0.5));
+        let z: Function<Complex<f64>> = VARIABLE!(z0);
+        let w: Function<Complex<f64>> = VARIABLE!(w0);
+        let e = exp();
+        let m = mul();
+        let a = abs();
+        let y: Function<Complex<f64>> = TERMINAL!(Complex::real(1.0));
+        z.link_to(&e);
+        e.link_to(&m);
+        w.link_to(&m);
+        m.followed_by(&a).followed_by(&y);
+        z.propagate_forward();
+        w.propagate_forward();
+        y.propagate_backward();
+        let loss = |z: Complex<f64>, w: Complex<f64>| (z.exp() * w).abs();
+        assert!((y.on_f(|a| a.outputs())[0].re - loss(z0, w0)).abs() < 1e-15);
+        let gz = z.on_b(|a| a.outputs())[0];
+        let gw = w.on_b(|a| a.outputs())[0];
+        assert!((gz - numerical_grad(|z| loss(z, w0), z0)).abs() < 1e-8);
+        assert!((gw - numerical_grad(|w| loss(z0, w), w0)).abs() < 1e-8);
+    }
+    #[test]
+    fn test_real_imag_conj_gradients() {
+        // L = re(conj(z)) + 3 im(conj(z)) = x - 3y
+        let z: Function<Complex<f64>> = VARIABLE!(Complex::new(2.0, 1.0), Complex::new(2.0, 1.0));
+        let c = conj();
+        let r = real();
+        let i = imag();
+        let y: Function<Complex<f64>> = TERMINAL!(Complex::real(1.0), Complex::real(3.0));
+        z.link_to(&c);
+        z.link_to(&c);
+        c.link_to(&r);
+        c.link_to(&i);
+        r.link_to(&y);
+        i.link_to(&y);
+        z.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(
+            y.on_f(|a| a.outputs()),
+            vec![Complex::real(2.0), Complex::real(-1.0)]
+        );
+        let g = z.on_b(|a| a.outputs());
+        assert_eq!(g[0] + g[1], Complex::new(1.0, -3.0));
+    }
+}
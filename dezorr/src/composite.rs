@@ -0,0 +1,361 @@
+//! Subgraphs packaged as single functions.
+//!
+//! [`compose`] chains the forward maps of two functions into a new one, like
+//! `followedBy` in the BQN prototype. [`Composite`] captures the graph a
+//! terminal depends on, up to some variables, and makes functions that run it
+//! with their inputs in place of the variables and their outputs taken from
+//! the terminal. Any other variable in the graph has to be a constant, captured
+//! with its current values; a parameter to be trained is passed as one more
+//! input instead, so that its values and gradients go through the ports of the
+//! instances. Branches of the graph to other terminals are left out. The
+//! backward of a composite reruns its forward, so nothing is kept between the
+//! passes.
+use {
+    crate::{
+        arrow::{ArrowType, VjpType},
+        func::{Function, FunctionOn},
+        subgraph::{key, select_outputs, Connected},
+        types::ContinuousDomain,
+        TFN,
+    },
+    std::rc::Rc,
+};
+
+/// `g` after `f`, as one function
+pub fn compose<'a, D: ContinuousDomain>(
+    f: &Function<'_, D>,
+    g: &Function<'_, D>,
+) -> Function<'a, D> {
+    let (f_forward, f_backward) = (f.forward_arrow(), f.backward_vjp());
+    let (g_forward, g_backward) = (g.forward_arrow(), g.backward_vjp());
+    let forward = f_forward.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[D]| g_forward(&forward(xs))),
+        TFN!(move |xs: &[D], gzs: &[D]| f_backward(xs, &g_backward(&f_forward(xs), gzs))),
+    )
+    .named(&format!("{} ∘ {}", g.name(), f.name()))
+}
+
+enum Role<D: ContinuousDomain> {
+    Input,
+    Constant(Vec<D>),
+    Step(Rc<ArrowType<D>>, Rc<VjpType<D>>, String),
+    Output,
+}
+
+struct Plan<D: ContinuousDomain> {
+    roles: Vec<Role<D>>,
+    /// `link_to` calls, in an order giving every function its lanes in the
+    /// order of the captured graph
+    links: Vec<(usize, usize)>,
+    /// the input variables with their numbers of lanes inside the graph, in
+    /// port order
+    inputs: Vec<(usize, usize)>,
+    output: usize,
+    output_lanes: usize,
+}
+
+/// the steps to rebuild a graph; see the [module documentation](self)
+#[derive(Clone)]
+pub struct Composite<D: ContinuousDomain>(Rc<Plan<D>>);
+
+impl<D: ContinuousDomain> Composite<D> {
+    /// capture the graph `output` depends on, up to `inputs`; lanes from it
+    /// to other functions are left out, with zero gradients
+    pub fn new<'a>(inputs: &[&'a Function<'a, D>], output: &'a Function<'a, D>) -> Self {
+        let connected = Connected::between(inputs, &[output]);
+        let kept = (0..connected.nodes.len())
+            .map(|i| connected.kept_lanes(i))
+            .collect::<Vec<_>>();
+        let roles = connected
+            .nodes
+            .iter()
+            .zip(&kept)
+            .enumerate()
+            .map(|(i, (f, kept))| {
+                if connected.is_input(i) {
+                    assert!(
+                        !kept.is_empty(),
+                        "the input {} does not reach the output",
+                        f.name()
+                    );
+                    Role::Input
+                } else if key(*f) == key(output) {
+                    Role::Output
+                } else if f.on_f(|a| a.is_coterminal()) {
+                    assert!(
+                        !f.requires_grad(),
+                        "the variable {} is not an input; pass it as one to train it",
+                        f.name()
+                    );
+                    let values = f.on_f(|a| a.outputs());
+                    Role::Constant(kept.iter().map(|k| values[*k].clone()).collect())
+                } else {
+                    let lanes = f.on_f(|a| a.codomain().len());
+                    let (forward, backward) =
+                        select_outputs(f.forward_arrow(), f.backward_vjp(), kept, lanes);
+                    Role::Step(forward, backward, f.name())
+                }
+            })
+            .collect();
+        Composite(Rc::new(Plan {
+            roles,
+            links: connected.links(),
+            inputs: inputs
+                .iter()
+                .map(|f| connected.index[&key(*f)])
+                .map(|i| (i, kept[i].len()))
+                .collect(),
+            output: connected.index[&key(output)],
+            output_lanes: output.on_b(|a| a.outputs().len()),
+        }))
+    }
+    /// rebuild the graph with the inputs `xs` and run it forward; with
+    /// upstream gradients `gys`, also run it backward and return the gradients
+    /// of the inputs instead
+    fn run(&self, xs: &[D], gys: Option<&[D]>) -> Vec<D> {
+        let plan = &self.0;
+        let lanes = plan.inputs.iter().map(|(_, k)| k).sum::<usize>();
+        assert_eq!(
+            xs.len(),
+            lanes,
+            "one input per captured lane of the input variables"
+        );
+        let mut values = vec![Vec::new(); plan.roles.len()];
+        let mut xs = xs.iter().cloned();
+        for (i, k) in &plan.inputs {
+            values[*i] = xs.by_ref().take(*k).collect();
+        }
+        let functions = plan
+            .roles
+            .iter()
+            .zip(values)
+            .map(|(role, values)| match role {
+                Role::Input => Function::coterminal(values),
+                Role::Constant(values) => {
+                    Function::coterminal(values.clone()).with_requires_grad(false)
+                }
+                Role::Step(forward, backward, name) => {
+                    let (forward, backward) = (forward.clone(), backward.clone());
+                    Function::with_vjp(
+                        TFN!(move |xs: &[D]| forward(xs)),
+                        TFN!(move |xs: &[D], gys: &[D]| backward(xs, gys)),
+                    )
+                    .named(name)
+                }
+                Role::Output => Function::terminal(vec![D::default(); plan.output_lanes]),
+            })
+            .collect::<Vec<_>>();
+        for (s, t) in &plan.links {
+            functions[*s].link_to(&functions[*t]);
+        }
+        for (f, role) in functions.iter().zip(plan.roles.iter()) {
+            if matches!(role, Role::Input | Role::Constant(_)) {
+                f.propagate_forward();
+            }
+        }
+        let output = &functions[plan.output];
+        match gys {
+            None => output.on_f(|a| a.outputs()),
+            Some(gys) => {
+                output.backward_with(gys.to_vec());
+                plan.inputs
+                    .iter()
+                    .flat_map(|(i, _)| functions[*i].on_b(|a| a.outputs()))
+                    .collect()
+            }
+        }
+    }
+    /// run the captured graph forward on `xs`
+    pub fn apply(&self, xs: &[D]) -> Vec<D> {
+        self.run(xs, None)
+    }
+    /// the gradients of the inputs `xs` given the gradients `gys` of the
+    /// outputs
+    pub fn vjp(&self, xs: &[D], gys: &[D]) -> Vec<D> {
+        self.run(xs, Some(gys))
+    }
+    /// a new function running the captured graph
+    pub fn instance<'a>(&self) -> Function<'a, D> {
+        let (forward, backward) = (self.clone(), self.clone());
+        Function::with_vjp(
+            TFN!(move |xs: &[D]| forward.apply(xs)),
+            TFN!(move |xs: &[D], gys: &[D]| backward.vjp(xs, gys)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CONSTANT, DFN, TERMINAL, VARIABLE};
+    fn mul<'a>() -> Function<'a, f64> {
+        Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        )
+        .named("mul")
+    }
+    fn exp<'a>() -> Function<'a, f64> {
+        Function::<f64>::new(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp())).named("exp")
+    }
+    fn square<'a>() -> Function<'a, f64> {
+        Function::<f64>::new(DFN!(|x: f64| x * x), DFN!(|x: f64| 2.0 * x)).named("square")
+    }
+    #[test]
+    fn test_compose() {
+        let f = compose(&square(), &exp());
+        assert_eq!(f.name(), "exp ∘ square");
+        // two uses of the same composition
+        let x: Function<f64> = VARIABLE!(0.5);
+        let (f1, f2) = (f.clone(), f);
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&f1).followed_by(&f2).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        let inner = 0.25f64.exp();
+        let value = (inner * inner).exp();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![value]);
+        let expected = value * 2.0 * inner * inner * 2.0 * 0.5;
+        assert!((x.on_b(|a| a.outputs())[0] - expected).abs() < 1e-12);
+    }
+    /// `(a, b) -> exp(2 a) b`, with the 2 held by a constant
+    fn block() -> Composite<f64> {
+        let a: Function<f64> = VARIABLE!(0.0);
+        let b: Function<f64> = VARIABLE!(0.0);
+        let w: Function<f64> = CONSTANT!(2.0);
+        let (m1, e, m2) = (mul(), exp(), mul());
+        let out: Function<f64> = TERMINAL!(1.0);
+        a.link_to(&m1);
+        w.link_to(&m1);
+        m1.followed_by(&e).link_to(&m2);
+        b.link_to(&m2);
+        m2.link_to(&out);
+        Composite::new(&[&a, &b], &out)
+    }
+    #[test]
+    fn test_composite_apply() {
+        let c = block();
+        let (a, b) = (0.25, 3.0);
+        assert_eq!(c.apply(&[a, b]), vec![0.5f64.exp() * b]);
+        assert_eq!(
+            c.vjp(&[a, b], &[2.0]),
+            vec![2.0 * 2.0 * 0.5f64.exp() * b, 2.0 * 0.5f64.exp()]
+        );
+        let f = c.instance();
+        assert_eq!(f.apply(&[a, b]), c.apply(&[a, b]));
+        assert_eq!(f.grad_at(&[a, b]), c.vjp(&[a, b], &[1.0]));
+    }
+    #[test]
+    fn test_composite_instances() {
+        // block(block(x, y), z)
+        let c = block();
+        let (x0, y0, z0) = (0.3, 1.5, -0.5);
+        let x: Function<f64> = VARIABLE!(x0);
+        let y: Function<f64> = VARIABLE!(y0);
+        let z: Function<f64> = VARIABLE!(z0);
+        let (inner, outer) = (c.instance(), c.instance());
+        let out: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&inner);
+        y.link_to(&inner);
+        inner.link_to(&outer);
+        z.link_to(&outer);
+        outer.link_to(&out);
+        for v in [&x, &y, &z] {
+            v.propagate_forward();
+        }
+        out.propagate_backward();
+        let u = (2.0 * x0).exp() * y0;
+        let value = (2.0 * u).exp() * z0;
+        assert_eq!(out.on_f(|a| a.outputs()), vec![value]);
+        let du = 2.0 * value;
+        let grads = [&x, &y, &z].map(|v| v.on_b(|a| a.outputs())[0]);
+        let expected = [du * 2.0 * u, du * (2.0 * x0).exp(), (2.0 * u).exp()];
+        for (g, e) in grads.iter().zip(expected) {
+            assert!((g - e).abs() < 1e-12, "{grads:?} {expected:?}");
+        }
+    }
+    #[test]
+    fn test_nested_composite() {
+        // a composite over a graph holding an instance of another
+        let c = block();
+        let a: Function<f64> = VARIABLE!(0.0, 0.0);
+        let inner = c.instance();
+        let sq = square();
+        let out: Function<f64> = TERMINAL!(1.0);
+        a.link_to(&inner);
+        a.link_to(&inner);
+        inner.followed_by(&sq).followed_by(&out);
+        let nested = Composite::new(&[&a], &out).instance();
+        let x: Function<f64> = VARIABLE!(0.4, 0.4);
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&nested);
+        x.link_to(&nested);
+        nested.link_to(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        // (exp(2x) x)^2
+        let u = (0.8f64).exp() * 0.4;
+        assert!((y.on_f(|a| a.outputs())[0] - u * u).abs() < 1e-15);
+        let g = x.on_b(|a| a.outputs());
+        assert!((g[0] - 2.0 * u * 2.0 * u).abs() < 1e-12);
+        assert!((g[1] - 2.0 * u * 0.8f64.exp()).abs() < 1e-12);
+    }
+    #[test]
+    fn test_composite_parameter_input() {
+        // (x, w) -> w x, with the weight as an input of the instances
+        let (x, w): (Function<f64>, Function<f64>) = (VARIABLE!(0.0), VARIABLE!(0.0));
+        let m = mul();
+        let out: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        w.link_to(&m);
+        m.link_to(&out);
+        let c = Composite::new(&[&x, &w], &out);
+        let x: Function<f64> = VARIABLE!(3.0);
+        let w: Function<f64> = VARIABLE!(2.0);
+        let (f, y): (_, Function<f64>) = (c.instance(), TERMINAL!(1.0));
+        x.link_to(&f);
+        w.link_to(&f);
+        f.link_to(&y);
+        x.propagate_forward();
+        w.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(w.on_b(|a| a.outputs()), vec![3.0]);
+        // a later assign reaches the instance
+        w.assign(vec![5.0]);
+        x.propagate_forward();
+        w.propagate_forward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![15.0]);
+    }
+    #[test]
+    #[should_panic(expected = "pass it as one to train it")]
+    fn test_composite_free_variable() {
+        let x: Function<f64> = VARIABLE!(0.0);
+        let w: Function<f64> = VARIABLE!(2.0);
+        let m = mul();
+        let out: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        w.link_to(&m);
+        m.link_to(&out);
+        Composite::new(&[&x], &out);
+    }
+    #[test]
+    fn test_composite_branches() {
+        // exp(x^2), with x and 3 x also feeding other terminals
+        let x: Function<f64> = VARIABLE!(0.0, 0.0);
+        let split = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[0], 3.0 * xs[0]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![2.0 * xs[0] * gs[0] + 3.0 * gs[1]]),
+        );
+        let e = exp();
+        let (out, other, thrice): (Function<f64>, Function<f64>, Function<f64>) =
+            (TERMINAL!(1.0), TERMINAL!(1.0), TERMINAL!(1.0));
+        x.link_to(&other);
+        x.link_to(&split);
+        split.followed_by(&e).followed_by(&out);
+        split.link_to(&thrice);
+        let c = Composite::new(&[&x], &out);
+        assert_eq!(c.apply(&[0.5]), vec![0.25f64.exp()]);
+        assert_eq!(c.vjp(&[0.5], &[1.0]), vec![0.25f64.exp()]);
+    }
+}
@@ -0,0 +1,385 @@
+//! Full Jacobians and Hessians of graphs over [`Dual`] numbers.
+//!
+//! The inputs of a graph are the values of its variables and its outputs are
+//! the values reaching its terminals, each taken lane by lane in the order
+//! given. [`jacobian`] runs one forward pass per input, seeding the tangent of
+//! that input, when there are no more inputs than outputs, and otherwise one
+//! backward pass per output, seeding the terminals with a one-hot vector.
+//! [`hvp`] differentiates the backward pass by running it over the tangents of
+//! a forward pass along a direction, which gives a Hessian-vector product for
+//! the cost of two passes; [`hessian`] does so once per input.
+//!
+//! These take graphs built over `Dual<T>`, one number per lane, since the
+//! forward passes carry their tangents and the second derivatives are
+//! tangents of the backward pass. A graph over plain real numbers such as
+//! `f64` gets its Jacobian from [`reverse_jacobian`], by backward passes only,
+//! and has no second derivatives. Graphs over tensors are not supported:
+//! their lanes would need one tangent per element.
+//!
+//! Every variable has to reach an output. Afterwards the variables hold their
+//! values again and the forward pass is rerun with them; the terminals keep
+//! their seeds, since the backward passes take theirs per call.
+use crate::{
+    dual::Dual,
+    func::{Function, FunctionOn},
+    retain::retain_graph,
+    tensor::Tensor,
+    types::{ContinuousDomain, Float},
+};
+
+type Graph<'g, 'a, T> = [&'g Function<'a, Dual<T>>];
+
+/// the values of all the lanes of `fs`, read by `read`
+fn gather<D: ContinuousDomain>(
+    fs: &[&Function<'_, D>],
+    read: impl Fn(&Function<D>) -> Vec<D>,
+) -> Vec<D> {
+    fs.iter().flat_map(|f| read(f)).collect()
+}
+
+/// give each variable the values `values` lays out lane by lane
+fn assign<T: Float>(variables: &Graph<'_, '_, T>, values: &[Dual<T>]) {
+    let mut values = values.iter().copied();
+    for v in variables {
+        let lanes = v.on_f(|a| a.outputs().len());
+        v.assign(values.by_ref().take(lanes).collect());
+    }
+}
+
+fn forward<'a, T: Float>(variables: &Graph<'a, 'a, T>) {
+    for v in variables {
+        v.propagate_forward();
+    }
+}
+
+/// `values` with the tangent of lane `j` set to one and the others to zero
+fn tangent<T: Float>(values: &[Dual<T>], j: Option<usize>) -> Vec<Dual<T>> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| match Some(i) == j {
+            true => Dual::variable(x.re),
+            false => Dual::constant(x.re),
+        })
+        .collect()
+}
+
+/// the `i`th of `m` unit seeds
+fn unit<D: Float>(m: usize, i: usize) -> Vec<D> {
+    (0..m)
+        .map(|k| D::from_f64(if k == i { 1.0 } else { 0.0 }))
+        .collect()
+}
+
+fn restore<'a, T: Float>(variables: &Graph<'a, 'a, T>, values: &[Dual<T>]) {
+    assign(variables, values);
+    forward(variables);
+}
+
+/// the `[outputs, inputs]` matrix of the graph as a table of columns
+fn forward_mode<'a, T: Float>(
+    variables: &Graph<'a, 'a, T>,
+    outputs: &Graph<'a, 'a, T>,
+    values: &[Dual<T>],
+) -> Vec<Vec<T>> {
+    (0..values.len())
+        .map(|j| {
+            assign(variables, &tangent(values, Some(j)));
+            forward(variables);
+            gather(outputs, |y| y.on_f(|a| a.outputs()))
+                .iter()
+                .map(|y| y.eps)
+                .collect()
+        })
+        .collect()
+}
+
+/// the `[outputs, inputs]` matrix of a graph run forward, as a table of rows
+fn reverse_mode<'a, D: Float>(
+    variables: &[&'a Function<'a, D>],
+    outputs: &[&'a Function<'a, D>],
+    m: usize,
+) -> Vec<Vec<D>> {
+    let _graph = retain_graph();
+    (0..m)
+        .map(|i| {
+            let mut seeds = unit(m, i).into_iter();
+            let seeds = outputs
+                .iter()
+                .map(|y| {
+                    let lanes = y.on_b(|a| a.outputs().len());
+                    (*y, seeds.by_ref().take(lanes).collect())
+                })
+                .collect::<Vec<_>>();
+            Function::backward_from(&seeds);
+            gather(variables, |v| v.on_b(|a| a.outputs()))
+        })
+        .collect()
+}
+
+/// the derivatives of every output lane of `outputs` by every input lane of
+/// `variables` in a graph over dual numbers, as a `[outputs, inputs]` tensor
+pub fn jacobian<'a, T: Float>(
+    variables: &Graph<'a, 'a, T>,
+    outputs: &Graph<'a, 'a, T>,
+) -> Tensor<T> {
+    let values = gather(variables, |v| v.on_f(|a| a.outputs()));
+    let seeds = gather(outputs, |y| y.on_b(|a| a.outputs()));
+    let (m, n) = (seeds.len(), values.len());
+    let data = if n <= m {
+        let columns = forward_mode(variables, outputs, &values);
+        (0..m * n).map(|k| columns[k % n][k / n]).collect()
+    } else {
+        assign(variables, &tangent(&values, None));
+        forward(variables);
+        let rows = reverse_mode(variables, outputs, m);
+        rows.concat().iter().map(|g| g.re).collect()
+    };
+    restore(variables, &values);
+    Tensor::new(vec![m, n], data)
+}
+
+/// the derivatives of every output lane of `outputs` by every input lane of
+/// `variables` in a graph over real numbers, as a `[outputs, inputs]` tensor,
+/// from one forward pass and one backward pass per output lane
+pub fn reverse_jacobian<'a, D: Float>(
+    variables: &[&'a Function<'a, D>],
+    outputs: &[&'a Function<'a, D>],
+) -> Tensor<D> {
+    let seeds = gather(outputs, |y| y.on_b(|a| a.outputs()));
+    let n = variables
+        .iter()
+        .map(|v| v.on_f(|a| a.outputs().len()))
+        .sum();
+    for v in variables {
+        v.propagate_forward();
+    }
+    let rows = reverse_mode(variables, outputs, seeds.len());
+    Tensor::new(vec![seeds.len(), n], rows.concat())
+}
+
+/// the gradient of `output` by the inputs, differentiated along `direction`
+fn curvature<'a, T: Float>(
+    variables: &Graph<'a, 'a, T>,
+    output: &'a Function<'a, Dual<T>>,
+    direction: &[Dual<T>],
+) -> Vec<T> {
+    assign(variables, direction);
+    forward(variables);
+    output.backward();
+    gather(variables, |v| v.on_b(|a| a.outputs()))
+        .iter()
+        .map(|g| g.eps)
+        .collect()
+}
+
+/// run `f` on the input values of a graph with a single output lane, then put
+/// the graph back
+fn on_scalar_output<'a, T: Float, R>(
+    variables: &Graph<'a, 'a, T>,
+    output: &'a Function<'a, Dual<T>>,
+    f: impl FnOnce(&[Dual<T>]) -> R,
+) -> R {
+    let lanes = output.on_b(|a| a.outputs().len());
+    assert_eq!(lanes, 1, "second derivatives need a single output");
+    let values = gather(variables, |v| v.on_f(|a| a.outputs()));
+    let result = f(&values);
+    restore(variables, &values);
+    result
+}
+
+/// the second derivatives of the single output lane of `output` by every pair
+/// of input lanes of `variables`, as an `[inputs, inputs]` tensor
+pub fn hessian<'a, T: Float>(
+    variables: &Graph<'a, 'a, T>,
+    output: &'a Function<'a, Dual<T>>,
+) -> Tensor<T> {
+    on_scalar_output(variables, output, |values| {
+        let n = values.len();
+        let columns = (0..n)
+            .map(|j| curvature(variables, output, &tangent(values, Some(j))))
+            .collect::<Vec<_>>();
+        Tensor::new(
+            vec![n, n],
+            (0..n * n).map(|k| columns[k % n][k / n]).collect(),
+        )
+    })
+}
+
+/// the hessian of the single output lane of `output` times `v`, from one
+/// forward pass along `v` and one backward pass over its tangents
+pub fn hvp<'a, T: Float>(
+    variables: &Graph<'a, 'a, T>,
+    output: &'a Function<'a, Dual<T>>,
+    v: &[T],
+) -> Vec<T> {
+    on_scalar_output(variables, output, |values| {
+        assert_eq!(values.len(), v.len(), "one direction per input lane");
+        let direction = values
+            .iter()
+            .zip(v)
+            .map(|(x, v)| Dual::new(x.re, *v))
+            .collect::<Vec<_>>();
+        curvature(variables, output, &direction)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DFN, TERMINAL, TFN, VARIABLE};
+    type D = Dual<f64>;
+    fn mul<'a>() -> Function<'a, D> {
+        Function::with_vjp(
+            TFN!(|xs: &[D]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[D], gs: &[D]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        )
+        .named("mul")
+    }
+    fn exp<'a>() -> Function<'a, D> {
+        Function::<D>::new(DFN!(|x: D| x.exp()), DFN!(|x: D| x.exp())).named("exp")
+    }
+    fn sin<'a>() -> Function<'a, D> {
+        Function::<D>::new(DFN!(|x: D| x.sin()), DFN!(|x: D| x.cos())).named("sin")
+    }
+    /// `(x, y) -> (x y, exp(x), sin(y))`
+    fn spread<'a>() -> Function<'a, D> {
+        Function::with_vjp(
+            TFN!(|xs: &[D]| vec![xs[0] * xs[1], xs[0].exp(), xs[1].sin()]),
+            TFN!(|xs: &[D], gs: &[D]| vec![
+                gs[0] * xs[1] + gs[1] * xs[0].exp(),
+                gs[0] * xs[0] + gs[2] * xs[1].cos()
+            ]),
+        )
+    }
+    fn close(a: &Tensor<f64>, b: &[f64]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12) && a.len() == b.len()
+    }
+    #[test]
+    fn test_jacobian_forward_mode() {
+        let (x0, y0) = (0.5, -1.2);
+        let x: Function<D> = VARIABLE!(D::constant(x0));
+        let y: Function<D> = VARIABLE!(D::constant(y0));
+        let f = spread();
+        let out: Function<D> = TERMINAL!(D::from_f64(1.0), D::from_f64(1.0), D::from_f64(1.0));
+        x.link_to(&f);
+        y.link_to(&f);
+        f.link_to(&out);
+        f.link_to(&out);
+        f.link_to(&out);
+        let j = jacobian(&[&x, &y], &[&out]);
+        assert_eq!(j.shape(), &[3, 2]);
+        let expected = [y0, x0, x0.exp(), 0.0, 0.0, y0.cos()];
+        assert!(close(&j, &expected), "{j:?}");
+        // the reverse mode agrees
+        let rows = reverse_mode(&[&x, &y], &[&out], 3);
+        let rows = rows.concat().iter().map(|g| g.re).collect();
+        assert!(close(&Tensor::new(vec![3, 2], rows), &expected));
+    }
+    #[test]
+    fn test_jacobian_reverse_mode() {
+        // (x, y, z) -> (sin(x y), exp(z)) over two terminals
+        let (x0, y0, z0) = (0.3, 2.0, -0.4);
+        let x: Function<D> = VARIABLE!(D::constant(x0));
+        let y: Function<D> = VARIABLE!(D::constant(y0));
+        let z: Function<D> = VARIABLE!(D::constant(z0));
+        let (m, s, e) = (mul(), sin(), exp());
+        let out1: Function<D> = TERMINAL!(D::from_f64(1.0));
+        let out2: Function<D> = TERMINAL!(D::from_f64(1.0));
+        x.link_to(&m);
+        y.link_to(&m);
+        m.followed_by(&s).followed_by(&out1);
+        z.followed_by(&e).followed_by(&out2);
+        let j = jacobian(&[&x, &y, &z], &[&out1, &out2]);
+        let c = (x0 * y0).cos();
+        let expected = [y0 * c, x0 * c, 0.0, 0.0, 0.0, z0.exp()];
+        assert!(close(&j, &expected), "{j:?}");
+        let values = gather(&[&x, &y, &z], |v| v.on_f(|a| a.outputs()));
+        let columns = forward_mode(&[&x, &y, &z], &[&out1, &out2], &values);
+        assert!(close(
+            &Tensor::new(vec![3, 2], columns.concat()).transpose(),
+            &expected
+        ));
+        // the graph holds its values again
+        restore(&[&x, &y, &z], &values);
+        assert_eq!(
+            out1.on_f(|a| a.outputs()),
+            vec![D::constant((x0 * y0).sin())]
+        );
+        assert_eq!(x.on_f(|a| a.outputs()), vec![D::constant(x0)]);
+    }
+    #[test]
+    fn test_reverse_jacobian_f64() {
+        // (x, y, z) -> (x y, exp(z)) over plain numbers
+        let x: Function<f64> = VARIABLE!(0.3);
+        let y: Function<f64> = VARIABLE!(2.0);
+        let z: Function<f64> = VARIABLE!(-0.4);
+        let m: Function<f64> = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        );
+        let e: Function<f64> = Function::new(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp()));
+        let out1: Function<f64> = TERMINAL!(1.0);
+        let out2: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        y.link_to(&m);
+        m.link_to(&out1);
+        z.followed_by(&e).followed_by(&out2);
+        let j = reverse_jacobian(&[&x, &y, &z], &[&out1, &out2]);
+        assert!(
+            close(&j, &[2.0, 0.3, 0.0, 0.0, 0.0, (-0.4f64).exp()]),
+            "{j:?}"
+        );
+        assert_eq!(out2.on_b(|a| a.outputs()), vec![1.0]);
+    }
+    #[test]
+    fn test_hessian() {
+        // exp(x) y: [[exp(x) y, exp(x)], [exp(x), 0]]
+        let (x0, y0) = (0.7, 3.0);
+        let x: Function<D> = VARIABLE!(D::constant(x0));
+        let y: Function<D> = VARIABLE!(D::constant(y0));
+        let (e, m) = (exp(), mul());
+        let out: Function<D> = TERMINAL!(D::from_f64(1.0));
+        x.followed_by(&e).link_to(&m);
+        y.link_to(&m);
+        m.link_to(&out);
+        let h = hessian(&[&x, &y], &out);
+        assert_eq!(h.shape(), &[2, 2]);
+        let ex = x0.exp();
+        assert!(close(&h, &[ex * y0, ex, ex, 0.0]), "{h:?}");
+        assert_eq!(out.on_f(|a| a.outputs()), vec![D::constant(ex * y0)]);
+    }
+    #[test]
+    fn test_hvp() {
+        // x1 x2 y + sin(z), with x reaching the product through two lanes
+        let (x0, y0, z0) = (1.5, -0.5, 0.9);
+        let x: Function<D> = VARIABLE!(D::constant(x0), D::constant(x0));
+        let y: Function<D> = VARIABLE!(D::constant(y0));
+        let z: Function<D> = VARIABLE!(D::constant(z0));
+        let (m1, m2, s) = (mul(), mul(), sin());
+        let add: Function<D> = Function::with_vjp(
+            TFN!(|xs: &[D]| vec![xs[0] + xs[1]]),
+            TFN!(|_: &[D], gs: &[D]| vec![gs[0], gs[0]]),
+        );
+        let out: Function<D> = TERMINAL!(D::from_f64(1.0));
+        x.link_to(&m1);
+        x.link_to(&m1);
+        m1.link_to(&m2);
+        y.link_to(&m2);
+        m2.link_to(&add);
+        z.followed_by(&s).link_to(&add);
+        add.link_to(&out);
+        let variables: [&Function<D>; 3] = [&x, &y, &z];
+        let h = hessian(&variables, &out);
+        let v = [0.3, -1.0, 2.0, 0.5];
+        let hv = hvp(&variables, &out, &v);
+        for (i, hv) in hv.iter().enumerate() {
+            let row = (0..4).map(|j| h.get(&[i, j]) * v[j]).sum::<f64>();
+            assert!((hv - row).abs() < 1e-12);
+        }
+        // d/dx1 of the gradient (x2 y, x1 y, x1 x2, cos z)
+        assert!((hv[0] - (v[1] * y0 + v[2] * x0)).abs() < 1e-12);
+        assert!((hv[3] + v[3] * z0.sin()).abs() < 1e-12);
+        assert_eq!(out.on_b(|a| a.outputs()), vec![D::from_f64(1.0)]);
+    }
+}
@@ -0,0 +1,183 @@
+//! Dual numbers, which carry a directional derivative through a graph.
+//!
+//! A [`Dual`] is `re + eps ε` with `ε² = 0`, so any function built from the
+//! arithmetic and the [`Float`] functions below maps `x + v ε` to
+//! `f(x) + f'(x) v ε`. Running the forward pass of a graph over duals gives
+//! its values and a Jacobian-vector product at once; running the backward pass
+//! over the same duals differentiates the gradient itself.
+use {
+    crate::types::{ContinuousDomain, Float, Scalar},
+    std::{cmp::Ordering, fmt},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dual<T: Float> {
+    pub re: T,
+    pub eps: T,
+}
+
+impl<T: Float> Dual<T> {
+    pub fn new(re: T, eps: T) -> Self {
+        Dual { re, eps }
+    }
+    /// a value with no tangent
+    pub fn constant(re: T) -> Self {
+        Dual::new(re, T::default())
+    }
+    /// a value moving with unit speed
+    pub fn variable(re: T) -> Self {
+        Dual::new(re, T::from_f64(1.0))
+    }
+    /// the same value with the tangent `f'(re) * eps`
+    fn chain(&self, re: T, derivative: T) -> Self {
+        Dual::new(re, derivative * self.eps)
+    }
+}
+
+impl<T: Float> From<T> for Dual<T> {
+    fn from(re: T) -> Self {
+        Dual::constant(re)
+    }
+}
+
+impl<T: Float> std::ops::Add for Dual<T> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Dual::new(self.re + other.re, self.eps + other.eps)
+    }
+}
+
+impl<T: Float> std::ops::Sub for Dual<T> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Dual::new(self.re - other.re, self.eps - other.eps)
+    }
+}
+
+impl<T: Float> std::ops::Mul for Dual<T> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        Dual::new(
+            self.re * other.re,
+            self.re * other.eps + self.eps * other.re,
+        )
+    }
+}
+
+impl<T: Float> std::ops::Div for Dual<T> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        Dual::new(
+            self.re / other.re,
+            (self.eps * other.re - self.re * other.eps) / (other.re * other.re),
+        )
+    }
+}
+
+impl<T: Float> std::ops::Neg for Dual<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Dual::new(-self.re, -self.eps)
+    }
+}
+
+/// ordered by value, then by tangent
+impl<T: Float> PartialOrd for Dual<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.re.partial_cmp(&other.re)? {
+            Ordering::Equal => self.eps.partial_cmp(&other.eps),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl<T: Float> fmt::Display for Dual<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.eps < T::default() { '-' } else { '+' };
+        let eps = if self.eps < T::default() {
+            -self.eps
+        } else {
+            self.eps
+        };
+        match f.precision() {
+            Some(p) => write!(f, "{:.p$}{sign}{:.p$}ε", self.re, eps),
+            None => write!(f, "{}{sign}{}ε", self.re, eps),
+        }
+    }
+}
+
+impl<T: Float> ContinuousDomain for Dual<T> {
+    fn is_finite(&self) -> bool {
+        self.re.to_f64().is_finite() && self.eps.to_f64().is_finite()
+    }
+}
+
+impl<T: Float> Scalar for Dual<T> {
+    fn to_f64(&self) -> f64 {
+        self.re.to_f64()
+    }
+}
+
+impl<T: Float> Float for Dual<T> {
+    fn from_f64(x: f64) -> Self {
+        Dual::constant(T::from_f64(x))
+    }
+    fn exp(self) -> Self {
+        let e = self.re.exp();
+        self.chain(e, e)
+    }
+    fn ln(self) -> Self {
+        self.chain(self.re.ln(), T::from_f64(1.0) / self.re)
+    }
+    fn sin(self) -> Self {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+    fn cos(self) -> Self {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+    fn sqrt(self) -> Self {
+        let s = self.re.sqrt();
+        self.chain(s, T::from_f64(0.5) / s)
+    }
+    fn hypot(self, other: Self) -> Self {
+        let h = self.re.hypot(other.re);
+        Dual::new(h, (self.re * self.eps + other.re * other.eps) / h)
+    }
+    /// the angle of `(other, self)`
+    fn atan2(self, other: Self) -> Self {
+        let r2 = self.re * self.re + other.re * other.re;
+        Dual::new(
+            self.re.atan2(other.re),
+            (other.re * self.eps - self.re * other.eps) / r2,
+        )
+    }
+    /// the next value, with the same tangent
+    fn next_up(self) -> Self {
+        Dual::new(self.re.next_up(), self.eps)
+    }
+    fn next_down(self) -> Self {
+        Dual::new(self.re.next_down(), self.eps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_dual_derivatives() {
+        // d/dx [x sin(x) / (1 + x^2)] at x = 0.7
+        let f = |x: Dual<f64>| x * x.sin() / (Dual::from_f64(1.0) + x * x);
+        let x = 0.7f64;
+        let d = f(Dual::variable(x));
+        let exact = ((x.sin() + x * x.cos()) * (1.0 + x * x) - 2.0 * x * x * x.sin())
+            / (1.0 + x * x).powi(2);
+        assert_eq!(d.re, x * x.sin() / (1.0 + x * x));
+        assert!((d.eps - exact).abs() < 1e-15);
+        let g = Dual::variable(3.0).hypot(Dual::constant(4.0));
+        assert_eq!(g, Dual::new(5.0, 0.6));
+        let a = Dual::constant(1.0f64).atan2(Dual::variable(1.0));
+        assert!((a.eps + 0.5).abs() < 1e-15);
+        assert_eq!(Dual::variable(4.0).sqrt(), Dual::new(2.0, 0.25));
+        assert_eq!(format!("{:.1}", Dual::new(1.0, -2.0)), "1.0-2.0ε");
+    }
+}
@@ -2,11 +2,19 @@
 
 use {
     crate::{
-        arrow::{Arrow, ArrowType, Connection},
-        types::ContinuousDomain,
+        anomaly::{is_detecting, Anomaly, Pass},
+        arrow::{Arrow, ArrowType, Connection, VjpType},
+        infer::ShapeRule,
+        retain::{is_freeing_outputs, is_retaining_graph},
+        types::{ContinuousDomain, OnesLike},
         DFN,
     },
-    std::{cell::RefCell, collections::VecDeque},
+    std::{
+        cell::RefCell,
+        collections::{HashMap, HashSet, VecDeque},
+        rc::Rc,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
 };
 
 #[macro_export]
@@ -16,6 +24,14 @@ macro_rules! VARIABLE {
     };
 }
 
+/// a variable that takes no gradient
+#[macro_export]
+macro_rules! CONSTANT {
+    ($($c: expr),+) => {
+        Function::coterminal(vec![$($c),*]).with_requires_grad(false)
+    };
+}
+
 #[macro_export]
 macro_rules! TERMINAL {
     ($($c: expr),+) => {
@@ -27,20 +43,52 @@ pub trait FunctionOn<'a, D: ContinuousDomain> {
     fn on_f<T>(&self, f: impl Fn(&Arrow<'a, D>) -> T) -> T;
     fn on_b<T>(&self, f: impl Fn(&Arrow<'a, D>) -> T) -> T;
     fn new(arrow: Option<ArrowType<D>>, coarrow: Option<ArrowType<D>>) -> Self;
+    fn with_vjp(arrow: Option<ArrowType<D>>, vjp: Option<VjpType<D>>) -> Self;
     fn coterminal(value: Vec<D>) -> Self;
     fn terminal(value: Vec<D>) -> Self;
     fn is_coterminal(&'a self) -> bool;
     fn link_to(&'a self, other: &'a Self);
     fn propagate_forward(&'a self);
     fn propagate_backward(&'a self);
-    fn followed_by(&'a self, other: &'a Self) -> &Self;
+    fn followed_by(&'a self, other: &'a Self) -> &'a Self;
     fn numerical_diff(&self, x: &[D], eps: &D) -> D;
 }
 
-#[derive(Debug, Default)]
 struct FunctionBody<'a, D: ContinuousDomain> {
     f: Arrow<'a, D>,
     b: Arrow<'a, D>,
+    name: Option<String>,
+    /// the creation index, naming the function when it has no name
+    id: usize,
+    shape: Option<Rc<ShapeRule>>,
+    requires_grad: Option<bool>,
+    retain_grad: bool,
+}
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+impl<D: ContinuousDomain> Default for FunctionBody<'_, D> {
+    fn default() -> Self {
+        FunctionBody {
+            f: Arrow::default(),
+            b: Arrow::default(),
+            name: None,
+            id: CREATED.fetch_add(1, Ordering::Relaxed),
+            shape: None,
+            requires_grad: None,
+            retain_grad: false,
+        }
+    }
+}
+
+impl<'a, D: ContinuousDomain + std::fmt::Debug> std::fmt::Debug for FunctionBody<'a, D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("FunctionBody")
+            .field("name", &self.name)
+            .field("f", &self.f)
+            .field("b", &self.b)
+            .finish()
+    }
 }
 
 #[derive(Debug)]
@@ -48,44 +96,494 @@ pub struct Function<'a, D: ContinuousDomain>(RefCell<FunctionBody<'a, D>>);
 
 impl<D: ContinuousDomain> Clone for Function<'_, D> {
     fn clone(&self) -> Self {
+        let body = self.0.borrow();
         Function(RefCell::new(FunctionBody {
-            f: self.0.borrow().f.clone(),
-            b: self.0.borrow().b.clone(),
+            f: body.f.clone(),
+            b: body.b.clone(),
+            name: body.name.clone(),
+            shape: body.shape.clone(),
+            requires_grad: body.requires_grad,
+            retain_grad: body.retain_grad,
+            ..FunctionBody::default()
         }))
     }
 }
 
 impl<'a, D: ContinuousDomain> Function<'a, D> {
+    /// give the function a name to report it by
+    pub fn named(self, name: &str) -> Self {
+        self.0.borrow_mut().name = Some(name.to_string());
+        self
+    }
+    /// the given name, or the kind of the function with its creation index,
+    /// such as `function#12`
+    pub fn name(&self) -> String {
+        let body = self.0.borrow();
+        let kind = match &body.name {
+            Some(name) => return name.clone(),
+            None if body.f.is_coterminal() => "variable",
+            None if body.f.arrow.is_none() => "terminal",
+            None => "function",
+        };
+        format!("{kind}#{}", body.id)
+    }
+    /// set how the shapes of the outputs follow from those of the inputs
+    pub fn with_shape_rule(self, rule: ShapeRule) -> Self {
+        self.0.borrow_mut().shape = Some(Rc::new(rule));
+        self
+    }
+    pub fn shape_rule(&self) -> Option<Rc<ShapeRule>> {
+        self.0.borrow().shape.clone()
+    }
+    /// fix whether gradients flow back into the function, instead of inferring
+    /// it from its inputs
+    pub fn with_requires_grad(self, flag: bool) -> Self {
+        self.set_requires_grad(flag);
+        self
+    }
+    /// freeze or unfreeze the function in a graph already built
+    pub fn set_requires_grad(&self, flag: bool) {
+        self.0.borrow_mut().requires_grad = Some(flag);
+    }
+    /// whether the backward pass reaches the function: a variable does unless
+    /// it is a constant, and any other function does when one of its inputs does
+    pub fn requires_grad(&self) -> bool {
+        self.needs_grad(&mut HashMap::new())
+    }
+    /// walk the sources depth first with a stack rather than recursively, as
+    /// graphs may be long chains
+    fn needs_grad(&self, memo: &mut HashMap<usize, bool>) -> bool {
+        let key = |f: &Self| f as *const Self as usize;
+        let mut stack = vec![(self, false)];
+        while let Some((f, expanded)) = stack.pop() {
+            if memo.contains_key(&key(f)) {
+                continue;
+            }
+            let flag = f.0.borrow().requires_grad;
+            let sources = f.on_f(|a| a.domain().iter().map(|c| c.source()).collect::<Vec<_>>());
+            let needed = match flag {
+                Some(flag) => flag,
+                None if f.on_f(|a| a.is_coterminal()) => true,
+                None if expanded => sources.iter().any(|s| memo[&key(s)]),
+                None => {
+                    stack.push((f, true));
+                    stack.extend(sources.into_iter().map(|s| (s, false)));
+                    continue;
+                }
+            };
+            memo.insert(key(f), needed);
+        }
+        memo[&key(self)]
+    }
+    /// keep the gradients of the function after the backward pass has used them
+    pub fn retain_grad(&self) {
+        self.0.borrow_mut().retain_grad = true;
+    }
+    /// the output shapes for the given input shapes; without a rule every
+    /// input passes its shape to the output in the same position
+    pub fn infer_shape(&self, inputs: &[Vec<usize>]) -> Result<Vec<Vec<usize>>, String> {
+        match &self.0.borrow().shape {
+            Some(rule) => rule(inputs),
+            None => Ok(inputs.to_vec()),
+        }
+    }
+    /// the forward map of the function
+    pub fn forward_arrow(&self) -> Rc<ArrowType<D>> {
+        self.on_f(|a| a.arrow.clone())
+            .expect("the function has no forward map")
+    }
+    /// the backward of the function as a map from its inputs and upstream
+    /// gradients, turning derivatives into one where needed
+    pub fn backward_vjp(&self) -> Rc<VjpType<D>> {
+        self.on_b(|a| match (&a.vjp, &a.arrow) {
+            (Some(vjp), _) => vjp.clone(),
+            (None, Some(derivative)) => {
+                let derivative = derivative.clone();
+                let vjp: VjpType<D> = Box::new(move |xs: &[D], gys: &[D]| {
+                    derivative(xs)
+                        .into_iter()
+                        .zip(gys.iter())
+                        .map(|(d, gy)| gy.clone() * d)
+                        .collect()
+                });
+                Rc::new(vjp)
+            }
+            (None, None) => panic!("the function has no backward map"),
+        })
+    }
+    /// a copy of a variable without connections, keeping the values of the
+    /// lanes at `lanes`
+    pub(crate) fn copy_lanes(&self, lanes: &[usize]) -> Self {
+        let copy = self.clone();
+        let values = self.on_f(|a| a.outputs());
+        let values = lanes.iter().map(|l| values[*l].clone()).collect();
+        copy.0.borrow_mut().f.set_values(values);
+        copy
+    }
+    /// link a variable to `target` through a new lane holding `value`
+    pub(crate) fn link_with_value(&'a self, target: &'a Self, value: D) {
+        assert!(
+            self.on_f(|a| a.is_coterminal()),
+            "only a variable holds values for its lanes"
+        );
+        self.link_to(target);
+        let f = &mut self.0.borrow_mut().f;
+        let mut values = f.outputs();
+        values.push(value);
+        f.set_values(values);
+    }
+    /// swap the forward and the derivatives of the function, keeping its
+    /// connections
+    pub fn replace_arrow(&self, arrow: Option<ArrowType<D>>, coarrow: Option<ArrowType<D>>) {
+        let body = &mut self.0.borrow_mut();
+        body.f.arrow = arrow.map(Rc::new);
+        body.b.arrow = coarrow.map(Rc::new);
+        body.b.vjp = None;
+    }
+    /// take the maps, name and shape rule of `other`, keeping the connections
+    /// of the function
+    pub fn replace_with(&self, other: &Function<'_, D>) {
+        let other = other.0.borrow();
+        let body = &mut self.0.borrow_mut();
+        body.f.arrow = other.f.arrow.clone();
+        body.b.arrow = other.b.arrow.clone();
+        body.b.vjp = other.b.vjp.clone();
+        body.name = other.name.clone();
+        body.shape = other.shape.clone();
+    }
+    /// the positions of the lanes from the function to `target`, in the
+    /// outputs of the function and in the inputs of `target`
+    fn lanes_to(&self, target: &Self) -> Vec<(usize, usize)> {
+        let ids = self.on_f(|a| {
+            a.codomain()
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| std::ptr::eq(c.target(), target))
+                .map(|(i, c)| (i, c.id()))
+                .collect::<Vec<_>>()
+        });
+        ids.into_iter()
+            .map(|(i, id)| {
+                let j = target.on_f(|a| a.domain().iter().position(|c| c.id() == id));
+                (i, j.expect("a connection is missing from its target"))
+            })
+            .collect()
+    }
+    /// remove every connection from the function to `target`, in both
+    /// directions; a variable drops the values and a terminal the seeds of
+    /// those lanes
+    pub fn unlink(&self, target: &Self) {
+        for (i, j) in self.lanes_to(target).into_iter().rev() {
+            {
+                let body = &mut self.0.borrow_mut();
+                body.f.remove_output(i);
+                body.b.remove_input(i);
+            }
+            let body = &mut target.0.borrow_mut();
+            body.f.remove_input(j);
+            body.b.remove_output(j);
+        }
+    }
+    /// route every connection from `source` to `target` through the function,
+    /// keeping the positions of the lanes at both ends
+    pub fn insert_between(&'a self, source: &'a Self, target: &'a Self) {
+        let lanes = source.lanes_to(target);
+        assert!(!lanes.is_empty(), "there is no connection to insert into");
+        for (i, j) in lanes {
+            let into = Connection::new(None, source, self);
+            let into_back = Connection::new(None, self, source);
+            let out = Connection::new(None, self, target);
+            let out_back = Connection::new(None, target, self);
+            {
+                let body = &mut source.0.borrow_mut();
+                body.f.replace_output(i, into.clone());
+                body.b.replace_input(i, into_back.clone());
+            }
+            {
+                let body = &mut self.0.borrow_mut();
+                body.f.add_input(into);
+                body.b.add_output(into_back);
+                body.f.add_output(out.clone());
+                body.b.add_input(out_back.clone());
+            }
+            let body = &mut target.0.borrow_mut();
+            body.f.replace_input(j, out);
+            body.b.replace_output(j, out_back);
+        }
+    }
+    /// run the forward of the function on `xs`, outside of any graph
+    pub fn apply(&self, xs: &[D]) -> Vec<D> {
+        self.forward_arrow()(xs)
+    }
+    /// the gradients of the inputs `xs` given the gradients `gys` of the
+    /// outputs, outside of any graph
+    pub fn vjp(&self, xs: &[D], gys: &[D]) -> Vec<D> {
+        self.backward_vjp()(xs, gys)
+    }
+    /// replace the values of a variable
+    pub fn assign(&self, values: Vec<D>) {
+        let f = &mut self.0.borrow_mut().f;
+        assert!(f.is_coterminal(), "only a variable can be assigned values");
+        assert_eq!(
+            f.outputs().len(),
+            values.len(),
+            "a variable keeps its number of values"
+        );
+        f.set_values(values);
+    }
+    /// replace the seeds a terminal starts `propagate_backward` with
+    pub fn reseed(&self, seeds: Vec<D>) {
+        let b = &mut self.0.borrow_mut().b;
+        assert!(b.is_coterminal(), "only a terminal can be reseeded");
+        assert_eq!(
+            b.outputs().len(),
+            seeds.len(),
+            "a terminal keeps its number of seeds"
+        );
+        b.set_values(seeds);
+    }
+    /// propagate forward, stopping at the first function with a non-finite output
+    pub fn try_propagate_forward(&'a self) -> Result<(), Anomaly> {
+        self.propagate(Pass::Forward, true)
+    }
+    /// propagate backward, stopping at the first function with a non-finite output
+    pub fn try_propagate_backward(&'a self) -> Result<(), Anomaly> {
+        self.propagate(Pass::Backward, true)
+    }
+    /// propagate backward from this terminal with `seed` as the gradients of
+    /// its outputs, in place of the values it was built with
+    pub fn backward_with(&'a self, seed: Vec<D>) {
+        Function::backward_from(&[(self, seed)]);
+    }
+    /// propagate backward from several terminals in one pass, as for the sum
+    /// of their outputs; seeds given for the same terminal add up, and the
+    /// terminals keep their own seeds for later passes
+    pub fn backward_from(outputs: &[(&'a Function<'a, D>, Vec<D>)]) {
+        let mut seeded: Vec<(&'a Function<'a, D>, Vec<D>)> = Vec::new();
+        for (y, seed) in outputs {
+            match seeded.iter_mut().find(|(z, _)| std::ptr::eq(*y, *z)) {
+                Some((_, sum)) => {
+                    assert_eq!(sum.len(), seed.len(), "one seed per output lane");
+                    *sum = sum
+                        .iter()
+                        .zip(seed.iter())
+                        .map(|(a, b)| a.clone() + b.clone())
+                        .collect();
+                }
+                None => seeded.push((*y, seed.clone())),
+            }
+        }
+        let kept = seeded
+            .iter()
+            .map(|(y, seed)| {
+                let kept = y.on_b(|a| a.outputs());
+                y.reseed(seed.clone());
+                kept
+            })
+            .collect::<Vec<_>>();
+        let starts = seeded.iter().map(|(y, _)| *y).collect::<Vec<_>>();
+        let result = Function::propagate_from(starts, Pass::Backward, is_detecting());
+        for ((y, _), seeds) in seeded.iter().zip(kept) {
+            y.reseed(seeds);
+        }
+        if let Err(anomaly) = result {
+            panic!("{anomaly}");
+        }
+    }
+    fn propagate(&'a self, pass: Pass, check: bool) -> Result<(), Anomaly> {
+        Function::propagate_from(vec![self], pass, check)
+    }
+    fn propagate_from(starts: Vec<&'a Self>, pass: Pass, check: bool) -> Result<(), Anomaly> {
+        // the backward pass leaves out what leads to no function needing a
+        // gradient
+        let running = match pass {
+            Pass::Forward => None,
+            Pass::Backward => Some(Function::backward_plan(&starts)),
+        };
+        let key = |f: &Self| f as *const Self as usize;
+        let needed = |f: &Self| running.as_ref().is_none_or(|r| r.contains(&key(f)));
+        let mut to_propagate = starts
+            .into_iter()
+            .filter(|f| needed(f))
+            .collect::<VecDeque<_>>();
+        while let Some(f) = to_propagate.pop_front() {
+            let next = match pass {
+                Pass::Forward => f.propagate_f(),
+                Pass::Backward => f.propagate_b(),
+            };
+            if let Some(fs) = next {
+                if check {
+                    f.check_finite(pass)?;
+                }
+                if pass == Pass::Backward {
+                    f.release();
+                }
+                for g in fs.into_iter().filter(|g| needed(g)) {
+                    to_propagate.push_back(g);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// the functions a backward pass from `starts` runs: those needing a
+    /// gradient that are starts or feed one that runs; the lanes from these
+    /// into a function left out get zero gradients, so that nothing waits for
+    /// a gradient that never comes
+    fn backward_plan(starts: &[&'a Self]) -> HashSet<usize> {
+        let key = |f: &Self| f as *const Self as usize;
+        // everything upstream of the starts, sources before their targets
+        let (mut order, mut seen) = (Vec::new(), HashSet::new());
+        let mut stack = starts.iter().map(|f| (*f, false)).collect::<Vec<_>>();
+        while let Some((f, expanded)) = stack.pop() {
+            if expanded {
+                order.push(f);
+            } else if seen.insert(key(f)) {
+                stack.push((f, true));
+                let sources = f.on_f(|a| a.domain().iter().map(|c| c.source()).collect::<Vec<_>>());
+                stack.extend(sources.into_iter().map(|s| (s, false)));
+            }
+        }
+        let mut memo = HashMap::new();
+        let mut running = HashSet::new();
+        for f in order.iter().rev() {
+            let start = starts.iter().any(|s| std::ptr::eq(*s, *f));
+            let fed = f.on_f(|a| {
+                a.codomain()
+                    .iter()
+                    .any(|c| running.contains(&key(c.target())))
+            });
+            if (start || fed) && f.needs_grad(&mut memo) {
+                running.insert(key(f));
+            }
+        }
+        for f in order.iter().filter(|f| running.contains(&key(f))) {
+            let body = f.0.borrow();
+            let outputs = body.f.outputs();
+            for ((c, back), y) in body.f.codomain().iter().zip(body.b.domain()).zip(&outputs) {
+                if !running.contains(&key(c.target())) {
+                    back.set_value(Some(y.zeros_like()));
+                }
+            }
+        }
+        running
+    }
+    fn check_finite(&self, pass: Pass) -> Result<(), Anomaly> {
+        let body = self.0.borrow();
+        let arrow = match pass {
+            Pass::Forward => &body.f,
+            Pass::Backward => &body.b,
+        };
+        let outputs = arrow.outputs();
+        if outputs.iter().all(|x| x.is_finite()) {
+            return Ok(());
+        }
+        let show = |xs: Vec<Option<D>>| {
+            xs.iter()
+                .map(|x| match x {
+                    Some(x) => format!("{x:?}"),
+                    None => "None".to_string(),
+                })
+                .collect()
+        };
+        Err(Anomaly {
+            node: self.name(),
+            pass,
+            inputs: show(body.f.inputs()),
+            upstream: match pass {
+                Pass::Forward => Vec::new(),
+                Pass::Backward => show(body.b.inputs()),
+            },
+            outputs: outputs.iter().map(|x| format!("{x:?}")).collect(),
+        })
+    }
+    /// drop what the backward of the function has used up: once it runs,
+    /// the backward of everything reading its outputs has run too
+    fn release(&self) {
+        let body = &mut self.0.borrow_mut();
+        if body.b.arrow.is_none() && body.b.vjp.is_none() {
+            // variables and terminals
+            return;
+        }
+        for c in body.b.domain() {
+            c.set_value(None);
+        }
+        if !is_retaining_graph() {
+            for c in body.f.domain() {
+                c.set_value(None);
+            }
+            if is_freeing_outputs() {
+                body.f.set_values(Vec::new());
+            }
+        }
+        if !body.retain_grad {
+            body.b.set_values(Vec::new());
+        }
+    }
     fn propagate_f(&'a self) -> Option<Vec<&'a Function<'a, D>>> {
         self.0.borrow_mut().f.propagate_forward()
     }
     fn propagate_b(&'a self) -> Option<Vec<&'a Function<'a, D>>> {
+        if !self.on_b(|a| a.is_applicable()) {
+            return None;
+        }
         let inputs = self.on_f(|a| a.inputs());
         let inputs = inputs
             .iter()
-            .map(|x| x.as_ref().unwrap())
+            .map(|x| {
+                x.as_ref().expect(
+                    "the forward inputs were freed by an earlier backward pass; \
+                     propagate forward again or retain the graph",
+                )
+            })
             .collect::<Vec<&D>>();
         self.0.borrow_mut().b.propagate_backward(&inputs)
     }
 }
 
+impl<'a, D: OnesLike> Function<'a, D> {
+    /// propagate backward from this terminal with a gradient of one on each
+    /// of its outputs, shaped like its seeds
+    pub fn backward(&'a self) {
+        let seeds = self.on_b(|a| a.outputs());
+        self.backward_with(seeds.iter().map(|s| s.ones_like()).collect());
+    }
+    /// the gradients of the inputs `xs` for a gradient of one on each output
+    pub fn grad_at(&self, xs: &[D]) -> Vec<D> {
+        let ones = self
+            .apply(xs)
+            .iter()
+            .map(|y| y.ones_like())
+            .collect::<Vec<_>>();
+        self.vjp(xs, &ones)
+    }
+}
+
 impl<'a, D: ContinuousDomain> FunctionOn<'a, D> for Function<'a, D> {
     fn new(arrow: Option<ArrowType<D>>, coarrow: Option<ArrowType<D>>) -> Self {
         Function(RefCell::new(FunctionBody {
             f: Arrow::new(arrow),
             b: Arrow::new(coarrow),
+            ..FunctionBody::default()
+        }))
+    }
+    /// build a function whose backward maps the upstream gradients directly
+    fn with_vjp(arrow: Option<ArrowType<D>>, vjp: Option<VjpType<D>>) -> Self {
+        Function(RefCell::new(FunctionBody {
+            f: Arrow::new(arrow),
+            b: Arrow::with_vjp(vjp),
+            ..FunctionBody::default()
         }))
     }
     fn coterminal(values: Vec<D>) -> Self {
         Function(RefCell::new(FunctionBody {
             f: Arrow::coterminal(values),
-            b: Arrow::default(),
+            ..FunctionBody::default()
         }))
     }
     fn terminal(values: Vec<D>) -> Self {
         Function(RefCell::new(FunctionBody {
-            f: Arrow::default(),
             b: Arrow::coterminal(values),
+            ..FunctionBody::default()
         }))
     }
     fn on_f<T>(&self, f: impl Fn(&Arrow<'a, D>) -> T) -> T {
@@ -121,25 +619,13 @@ impl<'a, D: ContinuousDomain> FunctionOn<'a, D> for Function<'a, D> {
         }
     }
     fn propagate_forward(&'a self) {
-        let mut to_propagate = VecDeque::new();
-        to_propagate.push_front(self);
-        while let Some(f) = to_propagate.pop_front() {
-            if let Some(fs) = f.propagate_f() {
-                for g in fs.iter() {
-                    to_propagate.push_back(*g);
-                }
-            }
+        if let Err(anomaly) = self.propagate(Pass::Forward, is_detecting()) {
+            panic!("{anomaly}");
         }
     }
     fn propagate_backward(&'a self) {
-        let mut to_propagate = VecDeque::new();
-        to_propagate.push_front(self);
-        while let Some(f) = to_propagate.pop_front() {
-            if let Some(fs) = f.propagate_b() {
-                for g in fs.iter() {
-                    to_propagate.push_back(*g);
-                }
-            }
+        if let Err(anomaly) = self.propagate(Pass::Backward, is_detecting()) {
+            panic!("{anomaly}");
         }
     }
     /// step 3: function composition
@@ -215,6 +701,7 @@ mod tests {
         assert_eq!(x.on_b(|a| a.outputs()), vec![1.0, 1.0]);
     }
     #[test]
+    #[allow(clippy::neg_multiply)]
     fn test_step_2_base4() {
         let x: Function<f64> = VARIABLE!(1.0, 2.0);
         let y: Function<f64> = TERMINAL!(1.0, 1.0);
@@ -347,4 +834,181 @@ mod tests {
         x.propagate_forward();
         assert_eq!(y.on_f(|a| a.outputs()), vec![5]);
     }
+    #[test]
+    fn test_terminals_take_latest_inputs() {
+        // a second pass replaces what the terminal and the variable received
+        let x: Function<f64> = VARIABLE!(2.0);
+        let f: Function<f64> = square::<f64>(&x);
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![4.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![4.0]);
+        x.assign(vec![3.0]);
+        x.propagate_forward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![9.0]);
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![6.0]);
+    }
+    /// `x -> (x^2, 3x)` as two outputs of one function
+    fn split_square_triple<'a>() -> Function<'a, f64> {
+        Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[0], 3.0 * xs[0]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![2.0 * xs[0] * gs[0] + 3.0 * gs[1]]),
+        )
+    }
+    #[test]
+    fn test_backward_with_seed() {
+        let x: Function<f64> = VARIABLE!(3.0);
+        let f: Function<f64> = square::<f64>(&x);
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.backward_with(vec![0.5]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![3.0]);
+        x.propagate_forward();
+        y.backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![6.0]);
+        // the terminal still holds the seed it was built with
+        assert_eq!(y.on_b(|a| a.outputs()), vec![1.0]);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![6.0]);
+    }
+    #[test]
+    fn test_backward_from_several_outputs() {
+        // L = 2 x^2 + 3x, with its terms reaching two terminals
+        let x: Function<f64> = VARIABLE!(1.5);
+        let f = split_square_triple();
+        let y1: Function<f64> = TERMINAL!(1.0);
+        let y2: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&f);
+        f.link_to(&y1);
+        f.link_to(&y2);
+        x.propagate_forward();
+        Function::backward_from(&[(&y1, vec![1.0]), (&y2, vec![1.0]), (&y1, vec![1.0])]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![4.0 * 1.5 + 3.0]);
+    }
+    #[test]
+    fn test_requires_grad_pruning() {
+        // y = x exp(c), with no gradient wanted for the data c
+        let x: Function<f64> = VARIABLE!(2.0);
+        let c: Function<f64> = CONSTANT!(0.5);
+        let e: Function<f64> = exp_f64(&c);
+        let m: Function<f64> = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        );
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        c.followed_by(&e).link_to(&m);
+        m.link_to(&y);
+        assert!(x.requires_grad() && m.requires_grad() && y.requires_grad());
+        assert!(!c.requires_grad() && !e.requires_grad());
+        x.propagate_forward();
+        c.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![0.5f64.exp()]);
+        assert!(!e.on_b(|a| a.is_applied()));
+        assert!(!c.on_b(|a| a.is_applied()));
+        // freezing x leaves nothing to do
+        x.set_requires_grad(false);
+        assert!(!y.requires_grad());
+        x.assign(vec![3.0]);
+        x.propagate_forward();
+        c.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![0.5f64.exp()]);
+    }
+    #[test]
+    fn test_frozen_intermediate() {
+        // y = x square(x), with the square frozen: x still gets the gradient
+        // of its trainable lane, and zero through the frozen one
+        let x: Function<f64> = VARIABLE!(3.0, 3.0);
+        let f: Function<f64> = square::<f64>(&x).with_requires_grad(false);
+        let m: Function<f64> = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        );
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        x.followed_by(&f).link_to(&m);
+        m.link_to(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![9.0, 0.0]);
+        assert!(!f.on_b(|a| a.is_applied()));
+    }
+    #[test]
+    fn test_apply_and_vjp() {
+        let x: Function<f64> = VARIABLE!(1.0);
+        let f: Function<f64> = square::<f64>(&x);
+        assert_eq!(f.apply(&[3.0, -2.0]), vec![9.0, 4.0]);
+        assert_eq!(f.vjp(&[3.0, -2.0], &[0.5, 1.0]), vec![3.0, -4.0]);
+        assert_eq!(f.grad_at(&[3.0]), vec![6.0]);
+        let g = split_square_triple();
+        assert_eq!(g.apply(&[2.0]), vec![4.0, 6.0]);
+        assert_eq!(g.grad_at(&[2.0]), vec![7.0]);
+    }
+    #[test]
+    fn test_unlink_and_relink() {
+        let x: Function<f64> = VARIABLE!(2.0, 3.0);
+        let f: Function<f64> = square::<f64>(&x);
+        let y: Function<f64> = TERMINAL!(1.0, 1.0);
+        let z: Function<f64> = TERMINAL!(0.5, 0.5);
+        x.link_to(&f);
+        x.link_to(&f);
+        f.link_to(&y);
+        f.link_to(&y);
+        f.unlink(&y);
+        assert!(y.on_f(|a| a.domain().is_empty()) && y.on_b(|a| a.codomain().is_empty()));
+        assert!(f.on_f(|a| a.codomain().is_empty()) && f.on_b(|a| a.domain().is_empty()));
+        f.link_to(&z);
+        f.link_to(&z);
+        x.propagate_forward();
+        z.propagate_backward();
+        assert_eq!(z.on_f(|a| a.outputs()), vec![4.0, 9.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![2.0, 3.0]);
+        // a variable keeps one value per remaining lane
+        x.unlink(&f);
+        assert_eq!(x.on_f(|a| a.outputs()), Vec::<f64>::new());
+    }
+    #[test]
+    fn test_replace_arrow() {
+        let x: Function<f64> = VARIABLE!(0.5);
+        let f: Function<f64> = square::<f64>(&x);
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&f).followed_by(&y);
+        f.replace_arrow(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp()));
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![0.5f64.exp()]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![0.5f64.exp()]);
+        let cube = Function::<f64>::new(DFN!(|x: f64| x.powi(3)), DFN!(|x: f64| 3.0 * x * x))
+            .named("cube");
+        f.replace_with(&cube);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(f.name(), "cube");
+        assert_eq!(x.on_b(|a| a.outputs()), vec![0.75]);
+    }
+    #[test]
+    fn test_insert_between() {
+        // a probe scaling by 10 between the square and the terminal
+        let x: Function<f64> = VARIABLE!(1.0, 2.0);
+        let f: Function<f64> = square::<f64>(&x);
+        let y: Function<f64> = TERMINAL!(1.0, 1.0);
+        let probe: Function<f64> = Function::new(DFN!(|x: f64| 10.0 * x), DFN!(|_| 10.0));
+        x.link_to(&f);
+        x.link_to(&f);
+        f.link_to(&y);
+        f.link_to(&y);
+        probe.insert_between(&f, &y);
+        x.propagate_forward();
+        assert_eq!(probe.on_f(|a| a.inputs()), vec![Some(1.0), Some(4.0)]);
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![10.0, 40.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![20.0, 40.0]);
+    }
 }
@@ -0,0 +1,165 @@
+//! Functions that change how gradients flow back through a graph.
+//!
+//! [`detach`] passes its inputs forward unchanged and needs no gradient, so
+//! the backward pass stops there and whatever feeds it only through it does
+//! no backward work. [`custom_gradient`] runs the forward of a whole subgraph,
+//! captured as a [`Composite`], but replaces its backward, which gives
+//! straight-through estimators or gradients clipped inside a subgraph.
+use crate::{
+    arrow::VjpType,
+    composite::Composite,
+    func::{Function, FunctionOn},
+    types::ContinuousDomain,
+    TFN,
+};
+
+/// the identity, left out of the backward pass; what feeds it gets zero
+/// gradients from it
+pub fn detach<'a, D: ContinuousDomain>() -> Function<'a, D> {
+    Function::with_vjp(
+        TFN!(|xs: &[D]| xs.to_vec()),
+        TFN!(|xs: &[D], _: &[D]| xs.iter().map(|x| x.zeros_like()).collect()),
+    )
+    .named("detach")
+    .with_requires_grad(false)
+}
+
+/// the forward of the captured subgraph `region` with `vjp` as the backward
+/// of the whole of it, taking the inputs of the region and the gradients of
+/// its outputs
+pub fn custom_gradient<'a, D: ContinuousDomain>(
+    region: &Composite<D>,
+    vjp: VjpType<D>,
+) -> Function<'a, D> {
+    let region = region.clone();
+    Function::with_vjp(TFN!(move |xs: &[D]| region.apply(xs)), Some(vjp)).named("custom_gradient")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ops::{add, reshape},
+        tensor::Tensor,
+        DFN, TERMINAL, VARIABLE,
+    };
+    use std::{cell::Cell, rc::Rc};
+    fn mul<'a>() -> Function<'a, f64> {
+        Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        )
+    }
+    #[test]
+    fn test_detach() {
+        // x * detach(x) differentiates as 3 x, not 2 x
+        let x: Function<f64> = VARIABLE!(3.0, 3.0);
+        let d = detach();
+        let m = mul();
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        x.followed_by(&d).link_to(&m);
+        m.link_to(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![9.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![3.0, 0.0]);
+        assert_eq!(d.name(), "detach");
+    }
+    #[test]
+    fn test_detach_tensor() {
+        // the zeros x gets through detach have the shape of x
+        let x: Function<Tensor<f64>> = VARIABLE!(
+            Tensor::new(vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]),
+            Tensor::new(vec![2, 2], vec![1.0, 2.0, 3.0, 4.0])
+        );
+        let (r1, r2, d) = (reshape(vec![4]), reshape(vec![4]), detach());
+        let add = add();
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::filled(&[4], 1.0));
+        x.followed_by(&r1).link_to(&add);
+        x.followed_by(&r2).followed_by(&d).link_to(&add);
+        add.link_to(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs())[0].shape(), &[4]);
+        assert_eq!(
+            x.on_b(|a| a.outputs()),
+            vec![Tensor::filled(&[2, 2], 1.0), Tensor::zeros(&[2, 2])]
+        );
+    }
+    #[test]
+    fn test_detach_stops_backward() {
+        // nothing behind the detach runs backward
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        let x: Function<f64> = VARIABLE!(2.0);
+        let square: Function<f64> = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[0]]),
+            TFN!(move |xs: &[f64], gs: &[f64]| {
+                counted.set(counted.get() + 1);
+                vec![2.0 * xs[0] * gs[0]]
+            }),
+        );
+        let d = detach();
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&square).followed_by(&d).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![4.0]);
+        assert_eq!(calls.get(), 0);
+        assert!(!x.on_b(|a| a.is_applied()));
+    }
+    #[test]
+    fn test_straight_through() {
+        // round(x)^2, passing the gradient of the square straight through round
+        let a: Function<f64> = VARIABLE!(0.0);
+        let round: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| x.round()), DFN!(|_: f64| 0.0)).named("round");
+        let out: Function<f64> = TERMINAL!(1.0);
+        a.followed_by(&round).followed_by(&out);
+        let st = custom_gradient(
+            &Composite::new(&[&a], &out),
+            Box::new(|_: &[f64], gs: &[f64]| gs.to_vec()),
+        );
+        let x: Function<f64> = VARIABLE!(1.7);
+        let square: Function<f64> = Function::new(DFN!(|x: f64| x * x), DFN!(|x: f64| 2.0 * x));
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.followed_by(&st).followed_by(&square).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![4.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![4.0]);
+        assert_eq!(st.name(), "custom_gradient");
+    }
+    #[test]
+    fn test_clipped_gradient() {
+        // exp(a) b with the gradients of the whole region clipped
+        let (a, b): (Function<f64>, Function<f64>) = (VARIABLE!(0.0), VARIABLE!(0.0));
+        let exp: Function<f64> = Function::new(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp()));
+        let m = mul();
+        let out: Function<f64> = TERMINAL!(1.0);
+        a.followed_by(&exp).link_to(&m);
+        b.link_to(&m);
+        m.link_to(&out);
+        let region = Composite::new(&[&a, &b], &out);
+        let inner = region.clone();
+        let clipped = custom_gradient(
+            &region,
+            Box::new(move |xs: &[f64], gs: &[f64]| {
+                let gs = inner.vjp(xs, gs);
+                gs.iter().map(|g| g.clamp(-1.0, 1.0)).collect()
+            }),
+        );
+        let (x, z): (Function<f64>, Function<f64>) = (VARIABLE!(2.0), VARIABLE!(0.5));
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&clipped);
+        z.link_to(&clipped);
+        clipped.link_to(&y);
+        x.propagate_forward();
+        z.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![0.5 * 2.0f64.exp()]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![1.0]);
+        assert_eq!(z.on_b(|a| a.outputs()), vec![1.0]);
+    }
+}
@@ -0,0 +1,320 @@
+//! 16-bit floats in software: `bfloat16` and IEEE 754 binary16.
+//!
+//! Values are stored as their bits. Conversions round to nearest, ties to
+//! even, and arithmetic promotes both operands to `f32`, which holds every
+//! 16-bit value exactly, then rounds the result back. Since `f32` carries more
+//! than twice the precision of either format, a sum, difference, product or
+//! quotient computed this way is correctly rounded.
+use {
+    crate::types::{ContinuousDomain, Float, Scalar},
+    std::{cmp::Ordering, fmt},
+};
+
+/// `bits` rounded to nearest even after dropping the lowest `shift` bits
+fn round_shift(bits: u32, shift: u32) -> u32 {
+    let kept = bits >> shift;
+    let rest = bits & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    if half < rest || (rest == half && kept & 1 == 1) {
+        kept + 1
+    } else {
+        kept
+    }
+}
+
+fn f32_to_bf16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        // keep the sign and the top of the payload, and make sure it stays a NaN
+        return (bits >> 16) as u16 | 0x0040;
+    }
+    // a carry out of the mantissa moves to the next binade, or to infinity
+    round_shift(bits, 16) as u16
+}
+
+fn bf16_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa == 0 {
+            0
+        } else {
+            0x0200 | (mantissa >> 13) as u16
+        };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exponent - 127 + 15;
+    if 0x1f <= e {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // subnormal in binary16; anything below half its least value rounds
+        // to zero
+        if e < -10 {
+            return sign;
+        }
+        return sign | round_shift(mantissa | 0x80_0000, (14 - e) as u32) as u16;
+    }
+    sign | round_shift(((e as u32) << 23) | mantissa, 13) as u16
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 * f32::from_bits((127 - 24) << 23);
+            f32::from_bits(sign | magnitude.to_bits())
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+/// `x` in `f32`, rounded to odd: an inexact result has its last bit set, so
+/// a second rounding to a narrower format is the same as a single one
+fn f64_to_f32_odd(x: f64) -> f32 {
+    let y = x as f32;
+    if !y.is_finite() || y as f64 == x || y.to_bits() & 1 == 1 {
+        return y;
+    }
+    if x < y as f64 {
+        y.next_down()
+    } else {
+        y.next_up()
+    }
+}
+
+macro_rules! impl_half {
+    ($name: ident, $doc: expr, $from: ident, $to: ident, $inf: expr) => {
+        #[doc = $doc]
+        #[derive(Clone, Copy, Default)]
+        pub struct $name(u16);
+
+        impl $name {
+            pub fn from_bits(bits: u16) -> Self {
+                $name(bits)
+            }
+            pub fn to_bits(self) -> u16 {
+                self.0
+            }
+            pub fn from_f32(x: f32) -> Self {
+                $name($from(x))
+            }
+            pub fn to_f32(self) -> f32 {
+                $to(self.0)
+            }
+            pub fn is_nan(self) -> bool {
+                $inf < self.0 & 0x7fff
+            }
+        }
+
+        impl From<f32> for $name {
+            fn from(x: f32) -> Self {
+                $name::from_f32(x)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(x: $name) -> Self {
+                x.to_f32()
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.to_f32() == other.to_f32()
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.to_f32().partial_cmp(&other.to_f32())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.to_f32(), f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.to_f32(), f)
+            }
+        }
+
+        impl std::ops::Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self {
+                $name(self.0 ^ 0x8000)
+            }
+        }
+
+        impl_half!(@binary $name, Add, add);
+        impl_half!(@binary $name, Sub, sub);
+        impl_half!(@binary $name, Mul, mul);
+        impl_half!(@binary $name, Div, div);
+
+        impl ContinuousDomain for $name {
+            fn is_finite(&self) -> bool {
+                self.to_f32().is_finite()
+            }
+        }
+
+        impl Scalar for $name {
+            fn to_f64(&self) -> f64 {
+                self.to_f32() as f64
+            }
+        }
+
+        impl Float for $name {
+            fn from_f64(x: f64) -> Self {
+                $name::from_f32(f64_to_f32_odd(x))
+            }
+            fn exp(self) -> Self {
+                $name::from_f32(self.to_f32().exp())
+            }
+            fn ln(self) -> Self {
+                $name::from_f32(self.to_f32().ln())
+            }
+            fn sin(self) -> Self {
+                $name::from_f32(self.to_f32().sin())
+            }
+            fn cos(self) -> Self {
+                $name::from_f32(self.to_f32().cos())
+            }
+            fn sqrt(self) -> Self {
+                $name::from_f32(self.to_f32().sqrt())
+            }
+            fn hypot(self, other: Self) -> Self {
+                $name::from_f32(self.to_f32().hypot(other.to_f32()))
+            }
+            fn atan2(self, other: Self) -> Self {
+                $name::from_f32(self.to_f32().atan2(other.to_f32()))
+            }
+            fn next_up(self) -> Self {
+                match self.0 {
+                    _ if self.is_nan() || self.0 == $inf => self,
+                    0 | 0x8000 => $name(1),
+                    b if b & 0x8000 == 0 => $name(b + 1),
+                    b => $name(b - 1),
+                }
+            }
+            fn next_down(self) -> Self {
+                -(-self).next_up()
+            }
+        }
+    };
+    (@binary $name: ident, $trait: ident, $method: ident) => {
+        impl std::ops::$trait for $name {
+            type Output = Self;
+            fn $method(self, other: Self) -> Self {
+                $name::from_f32(std::ops::$trait::$method(self.to_f32(), other.to_f32()))
+            }
+        }
+    };
+}
+
+impl_half!(
+    Bf16,
+    "`bfloat16`: the exponent range of `f32` with 8 bits of precision",
+    f32_to_bf16,
+    bf16_to_f32,
+    0x7f80
+);
+impl_half!(
+    F16,
+    "IEEE 754 binary16: 5 exponent bits and 11 bits of precision",
+    f32_to_f16,
+    f16_to_f32,
+    0x7c00
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        func::{Function, FunctionOn},
+        DFN, TERMINAL, VARIABLE,
+    };
+    #[test]
+    fn test_half_round_trip() {
+        for bits in 0..=u16::MAX {
+            let h = F16::from_bits(bits);
+            assert!(h.is_nan() || F16::from_f32(h.to_f32()).to_bits() == bits);
+            assert_eq!(h.is_nan(), h.to_f32().is_nan());
+            let b = Bf16::from_bits(bits);
+            assert!(b.is_nan() || Bf16::from_f32(b.to_f32()).to_bits() == bits);
+            assert_eq!(b.is_nan(), b.to_f32().is_nan());
+        }
+    }
+    #[test]
+    fn test_half_ties_to_even() {
+        // every midpoint between neighbours rounds to the one with an even mantissa
+        for bits in 0..0x7bffu16 {
+            let (a, b) = (F16::from_bits(bits), F16::from_bits(bits + 1));
+            let mid = (a.to_f32() + b.to_f32()) / 2.0;
+            let even = if bits & 1 == 0 { bits } else { bits + 1 };
+            assert_eq!(F16::from_f32(mid).to_bits(), even, "{mid}");
+            assert_eq!(F16::from_f32(mid.next_up()).to_bits(), bits + 1);
+            assert_eq!(F16::from_f32(mid.next_down()).to_bits(), bits);
+        }
+        assert_eq!(F16::from_f32(65504.0).to_f32(), 65504.0);
+        assert_eq!(F16::from_f32(65519.0).to_f32(), 65504.0);
+        assert_eq!(F16::from_f32(65520.0).to_f32(), f32::INFINITY);
+        assert_eq!(F16::from_f32(2.0f32.powi(-25)).to_bits(), 0);
+        assert_eq!(F16::from_f32(-1.5 * 2.0f32.powi(-25)).to_bits(), 0x8001);
+        assert_eq!(Bf16::from_f32(1.0 + 2.0f32.powi(-8)).to_f32(), 1.0);
+        assert_eq!(
+            Bf16::from_f32(1.0 + 3.0 * 2.0f32.powi(-8)).to_f32(),
+            1.0 + 2.0f32.powi(-6)
+        );
+        assert_eq!(Bf16::from_f32(f32::MAX).to_f32(), f32::INFINITY);
+        assert!(Bf16::from_f32(f32::NAN).is_nan());
+        // a double rounding through f32 would tie and round down to 1
+        let x = 1.0 + 2.0f64.powi(-11) + 2.0f64.powi(-40);
+        assert_eq!(F16::from_f64(x).to_f32(), 1.0 + 2.0f32.powi(-10));
+    }
+    #[test]
+    fn test_half_arithmetic() {
+        let (a, b) = (F16::from_f32(0.1), F16::from_f32(0.2));
+        assert_eq!(
+            (a + b).to_f32(),
+            F16::from_f32(a.to_f32() + b.to_f32()).to_f32()
+        );
+        assert_eq!(
+            F16::from_f32(2048.0) + F16::from_f32(1.0),
+            F16::from_f32(2048.0)
+        );
+        assert_eq!(-F16::from_f32(1.5), F16::from_f32(-1.5));
+        assert!(F16::from_f32(1.0) < F16::from_f32(1.001));
+        assert_eq!(
+            F16::from_f32(1.0).next_up().to_f32(),
+            1.0 + 2.0f32.powi(-10)
+        );
+        assert_eq!(F16::default().next_down().to_bits(), 0x8001);
+    }
+    #[test]
+    fn test_half_graph_accuracy() {
+        // f(x) = x^2 at x = 0.1 in f16 lands within an ulp of the exact values
+        let x: Function<F16> = VARIABLE!(F16::from_f32(0.1));
+        let f: Function<F16> = Function::new(DFN!(|x: F16| x * x), DFN!(|x: F16| x + x));
+        let y: Function<F16> = TERMINAL!(F16::from_f32(1.0));
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        let value = y.on_f(|a| a.outputs())[0].to_f32();
+        let grad = x.on_b(|a| a.outputs())[0].to_f32();
+        assert!(value != 0.01 && (value - 0.01).abs() < 0.01 * 2.0f32.powi(-10));
+        assert!((grad - 0.2).abs() < 0.2 * 2.0f32.powi(-10));
+    }
+}
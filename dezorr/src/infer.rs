@@ -0,0 +1,242 @@
+//! Shape inference over a graph, before any value is computed.
+//!
+//! Each function may carry a [`ShapeRule`] from its input shapes to its output
+//! shapes. [`infer_shapes`] starts from the shapes given for the variables and
+//! walks the graph in the order [`FunctionOn::propagate_forward`] would,
+//! applying the rules instead of the functions.
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        types::ContinuousDomain,
+    },
+    std::{
+        collections::{HashMap, HashSet, VecDeque},
+        fmt,
+    },
+};
+
+/// the output shapes of a function for its input shapes, or why they do not fit
+pub type ShapeRule = Box<dyn Fn(&[Vec<usize>]) -> Result<Vec<Vec<usize>>, String>>;
+
+/// shapes that do not fit the function they are given to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShapeError {
+    pub node: String,
+    pub inputs: Vec<Vec<usize>>,
+    pub message: String,
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} with inputs {}: {}",
+            self.node,
+            show_shapes(&self.inputs),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeShapes {
+    pub name: String,
+    pub inputs: Vec<Vec<usize>>,
+    pub outputs: Vec<Vec<usize>>,
+}
+
+/// the shapes at every function reached, in the order they were inferred
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary(pub Vec<NodeShapes>);
+
+impl Summary {
+    /// the shapes at the first function with this name
+    pub fn get(&self, name: &str) -> Option<&NodeShapes> {
+        self.0.iter().find(|n| n.name == name)
+    }
+}
+
+impl fmt::Display for Summary {
+    /// one line per function: its name, input shapes and output shapes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = std::iter::once(["node".to_string(), "inputs".into(), "outputs".into()])
+            .chain(self.0.iter().map(|n| {
+                [
+                    n.name.clone(),
+                    show_shapes(&n.inputs),
+                    show_shapes(&n.outputs),
+                ]
+            }))
+            .collect::<Vec<_>>();
+        let width = |i: usize| rows.iter().map(|r| r[i].chars().count()).max().unwrap();
+        let (w0, w1) = (width(0), width(1));
+        for r in rows.iter() {
+            writeln!(f, "{:<w0$}  {:<w1$}  {}", r[0], r[1], r[2])?;
+        }
+        Ok(())
+    }
+}
+
+fn show_shapes(shapes: &[Vec<usize>]) -> String {
+    if shapes.is_empty() {
+        return "-".to_string();
+    }
+    shapes
+        .iter()
+        .map(|s| format!("{s:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// the state of a pass of [`infer_shapes`]
+struct Pass<'a, D: ContinuousDomain> {
+    /// the shape carried by each connection reached, by [`crate::arrow::Connection::id`]
+    lanes: HashMap<usize, Vec<usize>>,
+    done: HashSet<*const Function<'a, D>>,
+    to_visit: VecDeque<&'a Function<'a, D>>,
+    summary: Summary,
+}
+
+impl<'a, D: ContinuousDomain> Pass<'a, D> {
+    /// the shapes on the inputs of `f`, once they are all known
+    fn inputs(&self, f: &'a Function<'a, D>) -> Option<Vec<Vec<usize>>> {
+        f.on_f(|a| {
+            a.domain()
+                .iter()
+                .map(|c| self.lanes.get(&c.id()).cloned())
+                .collect()
+        })
+    }
+    /// record the shapes at `f` and send its outputs on
+    fn visit(
+        &mut self,
+        f: &'a Function<'a, D>,
+        inputs: Vec<Vec<usize>>,
+        outputs: Vec<Vec<usize>>,
+    ) -> Result<(), ShapeError> {
+        self.done.insert(f);
+        let codomain = f.on_f(|a| a.codomain().to_vec());
+        if !codomain.is_empty() && codomain.len() != outputs.len() {
+            return Err(ShapeError {
+                node: f.name(),
+                inputs,
+                message: format!(
+                    "{} outputs for {} outgoing connections",
+                    outputs.len(),
+                    codomain.len()
+                ),
+            });
+        }
+        for (c, shape) in codomain.iter().zip(outputs.iter()) {
+            self.lanes.insert(c.id(), shape.clone());
+            self.to_visit.push_back(c.target());
+        }
+        self.summary.0.push(NodeShapes {
+            name: f.name(),
+            inputs,
+            outputs,
+        });
+        Ok(())
+    }
+}
+
+/// infer the shape at every function reachable from the given variables
+///
+/// Each variable comes with the shapes of its values. A function is visited
+/// once all of its inputs have a shape, and the first rule that fails stops
+/// the pass with an error naming that function.
+pub fn infer_shapes<'a, D: ContinuousDomain>(
+    variables: &[(&'a Function<'a, D>, Vec<Vec<usize>>)],
+) -> Result<Summary, ShapeError> {
+    let mut pass = Pass {
+        lanes: HashMap::new(),
+        done: HashSet::new(),
+        to_visit: VecDeque::new(),
+        summary: Summary::default(),
+    };
+    for (f, shapes) in variables.iter() {
+        pass.visit(f, Vec::new(), shapes.clone())?;
+    }
+    while let Some(f) = pass.to_visit.pop_front() {
+        if pass.done.contains(&(f as *const _)) {
+            continue;
+        }
+        let Some(inputs) = pass.inputs(f) else {
+            continue;
+        };
+        let outputs = f.infer_shape(&inputs).map_err(|message| ShapeError {
+            node: f.name(),
+            inputs: inputs.clone(),
+            message,
+        })?;
+        pass.visit(f, inputs, outputs)?;
+    }
+    Ok(pass.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ops, tensor::Tensor, TERMINAL, VARIABLE};
+    #[test]
+    fn test_infer_shapes() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::zeros(&[1])).named("x");
+        let w: Function<Tensor<f64>> = VARIABLE!(Tensor::zeros(&[1])).named("w");
+        let b: Function<Tensor<f64>> = VARIABLE!(Tensor::zeros(&[1])).named("b");
+        let m = ops::matmul().named("fc");
+        let a = ops::add();
+        let t = ops::tanh();
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::zeros(&[1])).named("terminal");
+        x.link_to(&m);
+        w.link_to(&m);
+        m.link_to(&a);
+        b.link_to(&a);
+        a.followed_by(&t).followed_by(&y);
+        let summary = infer_shapes(&[
+            (&x, vec![vec![32, 784]]),
+            (&w, vec![vec![784, 10]]),
+            (&b, vec![vec![10]]),
+        ])
+        .unwrap();
+        assert_eq!(summary.get("fc").unwrap().outputs, vec![vec![32, 10]]);
+        assert_eq!(summary.get("tanh").unwrap().outputs, vec![vec![32, 10]]);
+        assert_eq!(summary.get("terminal").unwrap().inputs, vec![vec![32, 10]]);
+        assert_eq!(
+            format!("{summary}").lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "node      inputs                outputs",
+                "x         -                     [32, 784]",
+                "w         -                     [784, 10]",
+            ]
+        );
+        // nothing was computed
+        assert!(!m.on_f(|a| a.is_applied()));
+        let error =
+            infer_shapes(&[(&x, vec![vec![32, 784]]), (&w, vec![vec![100, 10]])]).unwrap_err();
+        assert_eq!(error.node, "fc");
+        assert_eq!(
+            error.to_string(),
+            "fc with inputs [32, 784], [100, 10]: shapes [32, 784] and [100, 10] cannot be multiplied"
+        );
+    }
+    #[test]
+    fn test_infer_layout_shapes() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::zeros(&[1]));
+        let p = ops::permute(vec![2, 0, 1]);
+        let parts = ops::chunk(2, 0);
+        let y0: Function<Tensor<f64>> = TERMINAL!(Tensor::zeros(&[1]));
+        let y1: Function<Tensor<f64>> = TERMINAL!(Tensor::zeros(&[1]));
+        x.followed_by(&p).link_to(&parts);
+        parts.link_to(&y0);
+        parts.link_to(&y1);
+        let summary = infer_shapes(&[(&x, vec![vec![2, 3, 5]])]).unwrap();
+        assert_eq!(
+            summary.get("chunk").unwrap().outputs,
+            vec![vec![3, 2, 3], vec![2, 2, 3]]
+        );
+        let error = infer_shapes(&[(&x, vec![vec![2, 3]])]).unwrap_err();
+        assert_eq!(error.node, "permute");
+    }
+}
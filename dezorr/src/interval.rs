@@ -0,0 +1,314 @@
+//! Intervals and graph functions over them.
+//!
+//! Every operation returns an interval that contains the exact result for all
+//! points of its operands: lower bounds are rounded down and upper bounds up.
+//! `+`, `-`, `*`, `/` and `sqrt` are correctly rounded, so one step outwards
+//! suffices; `exp`, `ln`, `sin` and `cos` come from libm, which is accurate to
+//! an ulp, and are widened by two. Propagating a graph forward and backward
+//! over intervals thus encloses its values and gradients over the input box.
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        types::{ContinuousDomain, Float},
+        DFN,
+    },
+    std::{
+        f64::consts::{FRAC_PI_2, PI, TAU},
+        fmt,
+    },
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Interval<T: Float> {
+    lo: T,
+    hi: T,
+}
+
+fn down<T: Float>(x: T) -> T {
+    x.next_down()
+}
+
+fn up<T: Float>(x: T) -> T {
+    x.next_up()
+}
+
+fn min<T: Float>(a: T, b: T) -> T {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+fn max<T: Float>(a: T, b: T) -> T {
+    if a < b {
+        b
+    } else {
+        a
+    }
+}
+
+/// the products or quotients of all pairs of bounds, rounded outwards; an
+/// undefined corner such as `0 * inf` or `inf / inf` gives the whole line
+fn corners<T: Float>(a: &Interval<T>, b: &Interval<T>, op: fn(T, T) -> T) -> Interval<T> {
+    let values = [
+        op(a.lo, b.lo),
+        op(a.lo, b.hi),
+        op(a.hi, b.lo),
+        op(a.hi, b.hi),
+    ];
+    if values.iter().any(|v| v.partial_cmp(v).is_none()) {
+        return Interval::whole();
+    }
+    Interval {
+        lo: down(values.into_iter().reduce(min).unwrap()),
+        hi: up(values.into_iter().reduce(max).unwrap()),
+    }
+}
+
+/// the range of a function of period 2π over `[lo, hi]`, given where it peaks
+/// and where it bottoms out; a peak within rounding error counts as reached
+fn periodic_range(lo: f64, hi: f64, f: fn(f64) -> f64, peak: f64, trough: f64) -> (f64, f64) {
+    if (hi - lo).is_nan() || TAU <= hi - lo || 1e9 < lo.abs().max(hi.abs()) {
+        return (-1.0, 1.0);
+    }
+    let reaches = |at: f64| ((hi - at) / TAU + 1e-9).floor() >= ((lo - at) / TAU - 1e-9).ceil();
+    let (a, b) = (f(lo), f(hi));
+    (
+        if reaches(trough) { -1.0 } else { a.min(b) },
+        if reaches(peak) { 1.0 } else { a.max(b) },
+    )
+}
+
+impl<T: Float> Interval<T> {
+    pub fn new(lo: T, hi: T) -> Self {
+        assert!(lo <= hi, "the interval [{lo}, {hi}] is empty");
+        Interval { lo, hi }
+    }
+    /// the interval holding a single number
+    pub fn point(x: T) -> Self {
+        Interval::new(x, x)
+    }
+    /// the interval holding `x`, which is only known up to rounding
+    pub fn around(x: f64) -> Self {
+        let x = T::from_f64(x);
+        Interval::new(down(x), up(x))
+    }
+    /// the whole real line
+    pub fn whole() -> Self {
+        let inf = T::from_f64(f64::INFINITY);
+        Interval { lo: -inf, hi: inf }
+    }
+    pub fn lo(&self) -> T {
+        self.lo
+    }
+    pub fn hi(&self) -> T {
+        self.hi
+    }
+    pub fn width(&self) -> T {
+        up(self.hi - self.lo)
+    }
+    pub fn contains(&self, x: T) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+    /// whether every point of `other` lies in `self`
+    pub fn encloses(&self, other: &Self) -> bool {
+        self.lo <= other.lo && other.hi <= self.hi
+    }
+    pub fn exp(&self) -> Self {
+        Interval {
+            lo: max(T::default(), down(down(self.lo.exp()))),
+            hi: up(up(self.hi.exp())),
+        }
+    }
+    /// the logarithm of the positive part
+    pub fn ln(&self) -> Self {
+        assert!(
+            T::default() < self.hi,
+            "the logarithm of {self} is undefined"
+        );
+        Interval {
+            lo: down(down(max(T::default(), self.lo).ln())),
+            hi: up(up(self.hi.ln())),
+        }
+    }
+    /// the square root of the non-negative part
+    pub fn sqrt(&self) -> Self {
+        assert!(
+            T::default() <= self.hi,
+            "the square root of {self} is undefined"
+        );
+        Interval {
+            lo: max(T::default(), down(max(T::default(), self.lo).sqrt())),
+            hi: up(self.hi.sqrt()),
+        }
+    }
+    pub fn sin(&self) -> Self {
+        self.periodic(f64::sin, FRAC_PI_2, -FRAC_PI_2)
+    }
+    pub fn cos(&self) -> Self {
+        self.periodic(f64::cos, 0.0, PI)
+    }
+    fn periodic(&self, f: fn(f64) -> f64, peak: f64, trough: f64) -> Self {
+        let (lo, hi) = periodic_range(self.lo.to_f64(), self.hi.to_f64(), f, peak, trough);
+        let one = T::from_f64(1.0);
+        Interval {
+            lo: max(-one, down(down(T::from_f64(lo)))),
+            hi: min(one, up(up(T::from_f64(hi)))),
+        }
+    }
+}
+
+impl<T: Float> std::ops::Add for Interval<T> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Interval {
+            lo: down(self.lo + other.lo),
+            hi: up(self.hi + other.hi),
+        }
+    }
+}
+
+impl<T: Float> std::ops::Sub for Interval<T> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Interval {
+            lo: down(self.lo - other.hi),
+            hi: up(self.hi - other.lo),
+        }
+    }
+}
+
+impl<T: Float> std::ops::Mul for Interval<T> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        corners(&self, &other, |a, b| a * b)
+    }
+}
+
+impl<T: Float> std::ops::Div for Interval<T> {
+    type Output = Self;
+    /// a divisor containing zero gives the whole line
+    fn div(self, other: Self) -> Self {
+        if other.contains(T::default()) {
+            return Interval::whole();
+        }
+        corners(&self, &other, |a, b| a / b)
+    }
+}
+
+impl<T: Float> std::ops::Neg for Interval<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+}
+
+impl<T: Float> fmt::Display for Interval<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.precision() {
+            Some(p) => write!(f, "[{:.p$}, {:.p$}]", self.lo, self.hi),
+            None => write!(f, "[{}, {}]", self.lo, self.hi),
+        }
+    }
+}
+
+/// an interval is finite when both of its bounds are
+impl<T: Float> ContinuousDomain for Interval<T> {
+    fn is_finite(&self) -> bool {
+        self.lo.to_f64().is_finite() && self.hi.to_f64().is_finite()
+    }
+}
+
+pub fn exp<'a, T: Float>() -> Function<'a, Interval<T>> {
+    Function::<Interval<T>>::new(
+        DFN!(|x: Interval<T>| x.exp()),
+        DFN!(|x: Interval<T>| x.exp()),
+    )
+    .named("exp")
+}
+
+pub fn ln<'a, T: Float>() -> Function<'a, Interval<T>> {
+    Function::<Interval<T>>::new(
+        DFN!(|x: Interval<T>| x.ln()),
+        DFN!(|x: Interval<T>| Interval::point(T::from_f64(1.0)) / x),
+    )
+    .named("ln")
+}
+
+pub fn sin<'a, T: Float>() -> Function<'a, Interval<T>> {
+    Function::<Interval<T>>::new(
+        DFN!(|x: Interval<T>| x.sin()),
+        DFN!(|x: Interval<T>| x.cos()),
+    )
+    .named("sin")
+}
+
+pub fn sqrt<'a, T: Float>() -> Function<'a, Interval<T>> {
+    Function::<Interval<T>>::new(
+        DFN!(|x: Interval<T>| x.sqrt()),
+        DFN!(|x: Interval<T>| Interval::point(T::from_f64(0.5)) / x.sqrt()),
+    )
+    .named("sqrt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TERMINAL, VARIABLE};
+    #[test]
+    fn test_interval_arithmetic() {
+        let s = Interval::point(0.1) + Interval::point(0.2);
+        assert!(s.lo() < s.hi() && s.contains(0.3));
+        let a = Interval::new(-1.0, 2.0);
+        let b = Interval::new(3.0, 4.0);
+        assert!((a * b).encloses(&Interval::new(-4.0, 8.0)));
+        assert!((a - b).encloses(&Interval::new(-5.0, -1.0)));
+        assert_eq!((b / a).hi(), f64::INFINITY);
+        let unbounded = Interval::new(1.0, f64::INFINITY);
+        assert_eq!(Interval::point(0.0) * unbounded, Interval::whole());
+        assert_eq!(unbounded / unbounded, Interval::whole());
+        assert!((Interval::new(1.0, 2.0) * unbounded).encloses(&unbounded));
+        let third = Interval::<f32>::point(1.0) / Interval::point(3.0);
+        assert!(third.lo() < third.hi());
+    }
+    #[test]
+    fn test_interval_functions() {
+        let s = Interval::new(0.0, 4.0).sin();
+        assert_eq!(s.hi(), 1.0);
+        assert!(s.contains(4.0f64.sin()) && s.lo() > 4.0f64.sin() - 1e-15);
+        let c = Interval::new(3.0, 3.5).cos();
+        assert_eq!(c.lo(), -1.0);
+        assert!(Interval::new(-10.0, 10.0).sin() == Interval::new(-1.0, 1.0));
+        assert!(Interval::new(4.0, 9.0)
+            .sqrt()
+            .encloses(&Interval::new(2.0, 3.0)));
+        assert!(Interval::new(1.0, 2.0).ln().contains(0.0));
+        assert!(Interval::new(-1.0, 1.0).exp().lo() < (-1.0f64).exp());
+    }
+    #[test]
+    fn test_interval_graph_bounds() {
+        // f(x) = sqrt(exp(sin(x))) over the box [0.5, 0.7]
+        let x: Function<Interval<f64>> = VARIABLE!(Interval::new(0.5, 0.7));
+        let y: Function<Interval<f64>> = TERMINAL!(Interval::point(1.0));
+        let (a, b, c) = (sin(), exp(), sqrt());
+        x.followed_by(&a)
+            .followed_by(&b)
+            .followed_by(&c)
+            .followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        let value = y.on_f(|a| a.outputs())[0];
+        let grad = x.on_b(|a| a.outputs())[0];
+        for i in 0..=100 {
+            let t = 0.5 + 0.2 * i as f64 / 100.0;
+            let f = (t.sin() / 2.0).exp();
+            assert!(value.contains(f), "{f} is not in {value}");
+            let g = f * t.cos() / 2.0;
+            assert!(grad.contains(g), "{g} is not in {grad}");
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Elementwise kernels over `f32` and `f64` slices.
+//!
+//! Every kernel walks its slices in chunks of [`LANES`] independent elements
+//! so that the compiler can vectorize the chunk body, and finishes the tail
+//! one element at a time with the same formula, so results do not depend on
+//! where an element falls. `exp` is a polynomial rather than the libm call,
+//! which would keep the loop scalar; `sigmoid` is built on it, and `tanh` on
+//! an `expm1` from the same polynomial, which keeps its relative accuracy near
+//! zero.
+use crate::{tensor::Tensor, types::Scalar};
+
+pub const LANES: usize = 8;
+
+pub trait SimdFloat: Scalar + Copy {
+    /// `exp` of one element, with the formula the slice kernels use
+    fn exp_scalar(x: Self) -> Self;
+    /// `exp(x) - 1` of one element, accurate for small `x` too
+    fn expm1_scalar(x: Self) -> Self;
+    fn exp_slice(xs: &[Self], out: &mut [Self]);
+    fn tanh_slice(xs: &[Self], out: &mut [Self]);
+    fn sigmoid_slice(xs: &[Self], out: &mut [Self]);
+    fn add_slice(a: &[Self], b: &[Self], out: &mut [Self]);
+    fn mul_slice(a: &[Self], b: &[Self], out: &mut [Self]);
+    /// `gy * y` where `y = exp(x)`
+    fn exp_backward(gy: &[Self], y: &[Self], out: &mut [Self]);
+    /// `gy * (1 - y * y)` where `y = tanh(x)`
+    fn tanh_backward(gy: &[Self], y: &[Self], out: &mut [Self]);
+    /// `gy * y * (1 - y)` where `y = sigmoid(x)`
+    fn sigmoid_backward(gy: &[Self], y: &[Self], out: &mut [Self]);
+}
+
+#[inline(always)]
+fn unary<T: Copy>(xs: &[T], out: &mut [T], f: impl Fn(T) -> T) {
+    assert_eq!(xs.len(), out.len());
+    let mut xc = xs.chunks_exact(LANES);
+    let mut oc = out.chunks_exact_mut(LANES);
+    for (x, o) in (&mut xc).zip(&mut oc) {
+        for i in 0..LANES {
+            o[i] = f(x[i]);
+        }
+    }
+    for (x, o) in xc.remainder().iter().zip(oc.into_remainder()) {
+        *o = f(*x);
+    }
+}
+
+#[inline(always)]
+fn binary<T: Copy>(a: &[T], b: &[T], out: &mut [T], f: impl Fn(T, T) -> T) {
+    assert!(a.len() == out.len() && b.len() == out.len());
+    let mut ac = a.chunks_exact(LANES);
+    let mut bc = b.chunks_exact(LANES);
+    let mut oc = out.chunks_exact_mut(LANES);
+    for ((a, b), o) in (&mut ac).zip(&mut bc).zip(&mut oc) {
+        for i in 0..LANES {
+            o[i] = f(a[i], b[i]);
+        }
+    }
+    for ((a, b), o) in ac
+        .remainder()
+        .iter()
+        .zip(bc.remainder())
+        .zip(oc.into_remainder())
+    {
+        *o = f(*a, *b);
+    }
+}
+
+/// the range reduction of `exp`
+trait Reduce: Sized {
+    /// `2^n` in two factors and `r`, with `x = n ln 2 + r` and
+    /// `|r| <= ln 2 / 2`
+    fn reduce(x: Self) -> (Self, Self, Self);
+}
+
+macro_rules! impl_simd_float {
+    (
+        $t: ty, $bits: ty, $mantissa: expr, $bias: expr,
+        $min: expr, $max: expr, $ln2_hi: expr, $ln2_lo: expr,
+        [$($c: expr),+]
+    ) => {
+        impl Reduce for $t {
+            #[inline(always)]
+            fn reduce(x: Self) -> (Self, Self, Self) {
+                // 1.5 * 2^mantissa: adding it rounds to an integer held in the low bits
+                const SHIFTER: $t = (3u64 << ($mantissa - 1)) as $t;
+                let integer = |t: $t| (t.to_bits() as $bits).wrapping_sub(SHIFTER.to_bits() as $bits);
+                let x = x.clamp($min, $max);
+                let t = x * std::f64::consts::LOG2_E as $t + SHIFTER;
+                let n = t - SHIFTER;
+                let r = x - n * $ln2_hi - n * $ln2_lo;
+                // 2^n in two factors keeps each one a normal number
+                let k = integer(t);
+                let k1 = integer(n * 0.5 + SHIFTER);
+                let scale = |k: $bits| <$t>::from_bits((k + $bias).wrapping_shl($mantissa) as _);
+                (scale(k1), scale(k - k1), r)
+            }
+        }
+        impl SimdFloat for $t {
+            #[inline(always)]
+            fn exp_scalar(x: Self) -> Self {
+                let (scale1, scale2, r) = Self::reduce(x);
+                [$($c),+].iter().rev().fold(0.0, |p: $t, c| p * r + c) * scale1 * scale2
+            }
+            #[inline(always)]
+            fn expm1_scalar(x: Self) -> Self {
+                // 2^n exp(r) - 1 = 2^n (exp(r) - 1) + 2^n - 1, with the leading
+                // 1 left out of the polynomial
+                let (scale1, scale2, r) = Self::reduce(x);
+                let scale = scale1 * scale2;
+                let q = [$($c),+].iter().skip(1).rev().fold(0.0, |p: $t, c| p * r + c) * r;
+                scale * q + (scale - 1.0)
+            }
+            fn exp_slice(xs: &[Self], out: &mut [Self]) {
+                unary(xs, out, Self::exp_scalar);
+            }
+            fn tanh_slice(xs: &[Self], out: &mut [Self]) {
+                // tanh |x| = e / (e + 2) with e = expm1(2 |x|), which has no
+                // cancellation; tanh is 1 to the last bit well before 20
+                unary(xs, out, |x| {
+                    let e = Self::expm1_scalar(2.0 * x.abs().clamp(0.0, 20.0));
+                    (e / (e + 2.0)).copysign(x)
+                });
+            }
+            fn sigmoid_slice(xs: &[Self], out: &mut [Self]) {
+                unary(xs, out, |x| 1.0 / (1.0 + Self::exp_scalar(-x)));
+            }
+            fn add_slice(a: &[Self], b: &[Self], out: &mut [Self]) {
+                binary(a, b, out, |a, b| a + b);
+            }
+            fn mul_slice(a: &[Self], b: &[Self], out: &mut [Self]) {
+                binary(a, b, out, |a, b| a * b);
+            }
+            fn exp_backward(gy: &[Self], y: &[Self], out: &mut [Self]) {
+                binary(gy, y, out, |g, y| g * y);
+            }
+            fn tanh_backward(gy: &[Self], y: &[Self], out: &mut [Self]) {
+                binary(gy, y, out, |g, y| g * (1.0 - y * y));
+            }
+            fn sigmoid_backward(gy: &[Self], y: &[Self], out: &mut [Self]) {
+                binary(gy, y, out, |g, y| g * y * (1.0 - y));
+            }
+        }
+    };
+}
+
+// Taylor coefficients 1/k! up to the order where the remainder drops below an ulp
+impl_simd_float!(
+    f32,
+    i32,
+    23,
+    127,
+    -104.0,
+    89.0,
+    0.693_359_4,
+    -2.121_944_4e-4,
+    [
+        1.0,
+        1.0,
+        0.5,
+        1.0 / 6.0,
+        1.0 / 24.0,
+        1.0 / 120.0,
+        1.0 / 720.0,
+        1.0 / 5040.0
+    ]
+);
+impl_simd_float!(
+    f64,
+    i64,
+    52,
+    1023,
+    -746.0,
+    710.0,
+    6.931_471_803_691_238e-1,
+    1.908_214_929_270_587_7e-10,
+    [
+        1.0,
+        1.0,
+        0.5,
+        1.0 / 6.0,
+        1.0 / 24.0,
+        1.0 / 120.0,
+        1.0 / 720.0,
+        1.0 / 5040.0,
+        1.0 / 40320.0,
+        1.0 / 362880.0,
+        1.0 / 3628800.0,
+        1.0 / 39916800.0,
+        1.0 / 479001600.0,
+        1.0 / 6227020800.0
+    ]
+);
+
+/// apply a unary slice kernel to a tensor
+pub fn map_kernel<T: SimdFloat>(x: &Tensor<T>, kernel: fn(&[T], &mut [T])) -> Tensor<T> {
+    let x = x.contiguous();
+    let mut out = vec![T::default(); x.len()];
+    kernel(x.as_slice().unwrap(), &mut out);
+    Tensor::new(x.shape().to_vec(), out)
+}
+
+/// apply a binary slice kernel to two tensors after broadcasting them
+pub fn zip_kernel<T: SimdFloat>(
+    a: &Tensor<T>,
+    b: &Tensor<T>,
+    kernel: fn(&[T], &[T], &mut [T]),
+) -> Tensor<T> {
+    let (a, b) = if a.shape() == b.shape() {
+        (a.contiguous(), b.contiguous())
+    } else {
+        let shape = crate::tensor::broadcast_shapes(a.shape(), b.shape()).unwrap_or_else(|| {
+            panic!(
+                "shapes {:?} and {:?} cannot be broadcast",
+                a.shape(),
+                b.shape()
+            )
+        });
+        (
+            a.broadcast_to(&shape).contiguous(),
+            b.broadcast_to(&shape).contiguous(),
+        )
+    };
+    let mut out = vec![T::default(); a.len()];
+    kernel(a.as_slice().unwrap(), b.as_slice().unwrap(), &mut out);
+    Tensor::new(a.shape().to_vec(), out)
+}
+
+impl<T: SimdFloat> Tensor<T> {
+    pub fn exp(&self) -> Self {
+        map_kernel(self, T::exp_slice)
+    }
+    pub fn tanh(&self) -> Self {
+        map_kernel(self, T::tanh_slice)
+    }
+    pub fn sigmoid(&self) -> Self {
+        map_kernel(self, T::sigmoid_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn tanh(x: f64) -> f64 {
+        let mut out = [0.0];
+        f64::tanh_slice(&[x], &mut out);
+        out[0]
+    }
+    #[test]
+    fn test_exp_accuracy() {
+        let xs = (-7000..7000).map(|i| i as f64 / 10.0).collect::<Vec<_>>();
+        let mut out = vec![0.0; xs.len()];
+        f64::exp_slice(&xs, &mut out);
+        for (x, y) in xs.iter().zip(out.iter()) {
+            let e = x.exp();
+            assert!(
+                (y - e).abs() <= 4.0 * f64::EPSILON * e,
+                "exp({x}) = {y}, not {e}"
+            );
+        }
+        let xs = (-800..800).map(|i| i as f32 / 10.0).collect::<Vec<_>>();
+        let mut out = vec![0.0; xs.len()];
+        f32::exp_slice(&xs, &mut out);
+        for (x, y) in xs.iter().zip(out.iter()) {
+            let e = x.exp();
+            assert!(
+                (y - e).abs() <= 4.0 * f32::EPSILON * e,
+                "exp({x}) = {y}, not {e}"
+            );
+        }
+        assert_eq!(f64::exp_scalar(1000.0), f64::INFINITY);
+        assert_eq!(f64::exp_scalar(-1000.0), 0.0);
+        assert!(f64::exp_scalar(-740.0) > 0.0);
+        assert!(f32::exp_scalar(f32::NAN).is_nan());
+    }
+    #[test]
+    fn test_activation_kernels() {
+        let xs = (-37..37).map(|i| i as f64 / 3.0).collect::<Vec<_>>();
+        let (mut t, mut s) = (vec![0.0; xs.len()], vec![0.0; xs.len()]);
+        f64::tanh_slice(&xs, &mut t);
+        f64::sigmoid_slice(&xs, &mut s);
+        for (i, x) in xs.iter().enumerate() {
+            assert!((t[i] - x.tanh()).abs() <= 4.0 * f64::EPSILON * x.tanh().abs());
+            assert!((s[i] - 1.0 / (1.0 + (-x).exp())).abs() <= 4.0 * f64::EPSILON);
+        }
+        let mut g = vec![0.0; xs.len()];
+        f64::tanh_backward(&vec![2.0; xs.len()], &t, &mut g);
+        assert!((g[38] - 2.0 * (1.0 - (1.0f64 / 3.0).tanh().powi(2))).abs() <= 8.0 * f64::EPSILON);
+    }
+    #[test]
+    fn test_small_arguments() {
+        // tanh and expm1 keep their relative accuracy near zero
+        let xs = (-300..40)
+            .flat_map(|e| [1.0, -1.7].map(|m| m * 1.1f64.powi(e)))
+            .collect::<Vec<_>>();
+        let mut t = vec![0.0; xs.len()];
+        f64::tanh_slice(&xs, &mut t);
+        for (x, t) in xs.iter().zip(t) {
+            let (e, m) = (x.tanh(), f64::expm1_scalar(*x));
+            assert!(
+                (t - e).abs() <= 4.0 * f64::EPSILON * e.abs(),
+                "tanh({x}) = {t}, not {e}"
+            );
+            let e = x.exp_m1();
+            assert!(
+                (m - e).abs() <= 4.0 * f64::EPSILON * e.abs(),
+                "expm1({x}) = {m}, not {e}"
+            );
+        }
+        let xs = (-100..100).map(|e| 1.2f32.powi(e)).collect::<Vec<_>>();
+        let mut t = vec![0.0; xs.len()];
+        f32::tanh_slice(&xs, &mut t);
+        for (x, t) in xs.iter().zip(t) {
+            let e = x.tanh();
+            assert!(
+                (t - e).abs() <= 4.0 * f32::EPSILON * e,
+                "tanh({x}) = {t}, not {e}"
+            );
+        }
+        assert_eq!(tanh(0.0), 0.0);
+        assert!(tanh(f64::NAN).is_nan());
+        assert_eq!(tanh(f64::INFINITY), 1.0);
+        assert_eq!(tanh(-1000.0), -1.0);
+    }
+    #[test]
+    fn test_tensor_kernels() {
+        let a = Tensor::new(vec![2, 2], vec![0.0f32, 1.0, 2.0, 3.0]);
+        let b = Tensor::vector(vec![10.0f32, 100.0]);
+        assert_eq!(
+            zip_kernel(&a, &b, f32::mul_slice).to_vec(),
+            vec![0.0, 100.0, 20.0, 300.0]
+        );
+        assert_eq!(a.transpose().exp().to_vec()[1], f32::exp_scalar(2.0));
+    }
+}
@@ -1,4 +1,26 @@
+pub mod anomaly;
 pub mod arrow;
+pub mod complex;
+pub mod composite;
+pub mod derivatives;
+pub mod dual;
 pub mod func;
+pub mod gradient;
+pub mod half;
+pub mod infer;
+pub mod interval;
+pub mod kernels;
+pub mod mixed;
+pub mod npy;
+pub mod ops;
+pub mod print;
+pub mod rational;
+pub mod retain;
+pub mod shaped;
+pub mod subgraph;
+pub mod tensor;
+pub mod transform;
 pub mod types;
 pub mod var;
+
+pub use transform::{grad, value_and_grad, vmap};
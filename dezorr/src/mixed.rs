@@ -0,0 +1,193 @@
+//! Graphs that run partly in `f32` and partly in `f64`.
+//!
+//! A graph carries a single domain, so [`Mixed`] holds a tensor of either
+//! precision. The cast functions [`to_f32`] and [`to_f64`] change the precision
+//! of their inputs and pass gradients back in the precision each input had;
+//! [`dispatch`] lifts a pair of tensor functions to run on whichever precision
+//! arrives.
+use crate::{
+    func::{Function, FunctionOn},
+    tensor::Tensor,
+    types::ContinuousDomain,
+    TFN,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mixed {
+    F32(Tensor<f32>),
+    F64(Tensor<f64>),
+}
+
+impl Default for Mixed {
+    fn default() -> Self {
+        Mixed::F64(Tensor::default())
+    }
+}
+
+fn convert<T: ContinuousDomain, U: ContinuousDomain>(
+    t: &Tensor<T>,
+    f: impl Fn(&T) -> U,
+) -> Tensor<U> {
+    Tensor::new(t.shape().to_vec(), t.iter().map(f).collect())
+}
+
+impl Mixed {
+    pub fn is_f32(&self) -> bool {
+        matches!(self, Mixed::F32(_))
+    }
+    /// the values in `f32`, rounded if they were `f64`
+    pub fn to_f32(&self) -> Tensor<f32> {
+        match self {
+            Mixed::F32(t) => t.clone(),
+            Mixed::F64(t) => convert(t, |x| *x as f32),
+        }
+    }
+    pub fn to_f64(&self) -> Tensor<f64> {
+        match self {
+            Mixed::F32(t) => convert(t, |x| *x as f64),
+            Mixed::F64(t) => t.clone(),
+        }
+    }
+    /// the values in the precision of `like`
+    pub fn cast_like(&self, like: &Mixed) -> Mixed {
+        match like {
+            Mixed::F32(_) => Mixed::F32(self.to_f32()),
+            Mixed::F64(_) => Mixed::F64(self.to_f64()),
+        }
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($trait: ident, $method: ident) => {
+        impl std::ops::$trait for Mixed {
+            type Output = Self;
+            /// operands of different precisions are computed in `f64`
+            fn $method(self, other: Self) -> Self::Output {
+                match (self, other) {
+                    (Mixed::F32(a), Mixed::F32(b)) => Mixed::F32(std::ops::$trait::$method(a, b)),
+                    (a, b) => Mixed::F64(std::ops::$trait::$method(a.to_f64(), b.to_f64())),
+                }
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add);
+impl_binary_op!(Sub, sub);
+impl_binary_op!(Mul, mul);
+impl_binary_op!(Div, div);
+
+impl ContinuousDomain for Mixed {
+    fn is_finite(&self) -> bool {
+        match self {
+            Mixed::F32(t) => t.is_finite(),
+            Mixed::F64(t) => t.is_finite(),
+        }
+    }
+}
+
+fn cast<'a>(f: fn(&Mixed) -> Mixed) -> Function<'a, Mixed> {
+    Function::with_vjp(
+        TFN!(move |xs: &[Mixed]| xs.iter().map(f).collect()),
+        TFN!(|xs: &[Mixed], gys: &[Mixed]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| gy.cast_like(x))
+            .collect()),
+    )
+}
+
+pub fn to_f32<'a>() -> Function<'a, Mixed> {
+    cast(|x| Mixed::F32(x.to_f32())).named("to_f32")
+}
+
+pub fn to_f64<'a>() -> Function<'a, Mixed> {
+    cast(|x| Mixed::F64(x.to_f64())).named("to_f64")
+}
+
+/// run `single` when every input is `f32` and `double` otherwise, promoting
+/// the inputs to `f64`; gradients return in the precision of their input
+pub fn dispatch<'a>(
+    single: Function<'a, Tensor<f32>>,
+    double: Function<'a, Tensor<f64>>,
+) -> Function<'a, Mixed> {
+    let (forward32, backward32) = (single.forward_arrow(), single.backward_vjp());
+    let (forward64, backward64) = (double.forward_arrow(), double.backward_vjp());
+    let f = Function::with_vjp(
+        TFN!(move |xs: &[Mixed]| if xs.iter().all(Mixed::is_f32) {
+            let xs = xs.iter().map(Mixed::to_f32).collect::<Vec<_>>();
+            forward32(&xs).into_iter().map(Mixed::F32).collect()
+        } else {
+            let xs = xs.iter().map(Mixed::to_f64).collect::<Vec<_>>();
+            forward64(&xs).into_iter().map(Mixed::F64).collect()
+        }),
+        TFN!(
+            move |xs: &[Mixed], gys: &[Mixed]| if xs.iter().all(Mixed::is_f32) {
+                let ys = xs.iter().map(Mixed::to_f32).collect::<Vec<_>>();
+                let gys = gys.iter().map(Mixed::to_f32).collect::<Vec<_>>();
+                backward32(&ys, &gys).into_iter().map(Mixed::F32).collect()
+            } else {
+                let ys = xs.iter().map(Mixed::to_f64).collect::<Vec<_>>();
+                let gys = gys.iter().map(Mixed::to_f64).collect::<Vec<_>>();
+                backward64(&ys, &gys)
+                    .into_iter()
+                    .zip(xs.iter())
+                    .map(|(g, x)| Mixed::F64(g).cast_like(x))
+                    .collect()
+            }
+        ),
+    )
+    .named(&double.name());
+    match double.shape_rule() {
+        Some(rule) => f.with_shape_rule(Box::new(move |xs: &[Vec<usize>]| rule(xs))),
+        None => f,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ops, TERMINAL, VARIABLE};
+    #[test]
+    fn test_mixed_arithmetic() {
+        let a = Mixed::F32(Tensor::vector(vec![1.5f32, 2.0]));
+        let b = Mixed::F64(Tensor::scalar(0.1));
+        assert!((a.clone() * a.clone()).is_f32());
+        assert_eq!(a * b, Mixed::F64(Tensor::vector(vec![1.5 * 0.1, 0.2])));
+        let c = Mixed::F64(Tensor::scalar(1.0 + 1e-12));
+        assert_eq!(
+            c.cast_like(&Mixed::F32(Tensor::default()))
+                .to_f32()
+                .to_vec(),
+            vec![1.0]
+        );
+    }
+    #[test]
+    fn test_mixed_precision_graph() {
+        // tanh in f32, then a product with an f64 weight in f64
+        let x: Function<Mixed> = VARIABLE!(Mixed::F32(Tensor::vector(vec![0.5, -1.0])));
+        let w: Function<Mixed> = VARIABLE!(Mixed::F64(Tensor::scalar(3.0)));
+        let t = dispatch(ops::tanh(), ops::tanh());
+        let up = to_f64();
+        let m = dispatch(ops::mul(), ops::mul());
+        let y: Function<Mixed> = TERMINAL!(Mixed::F64(Tensor::vector(vec![1.0, 1.0])));
+        x.followed_by(&t).followed_by(&up).link_to(&m);
+        w.link_to(&m);
+        m.link_to(&y);
+        x.propagate_forward();
+        w.propagate_forward();
+        y.propagate_backward();
+        assert!(t.on_f(|a| a.outputs()[0].is_f32()));
+        let out = y.on_f(|a| a.outputs())[0].clone();
+        assert!(!out.is_f32());
+        assert!((out.to_f64().to_vec()[0] - 3.0 * 0.5f64.tanh()).abs() < 1e-6);
+        let gx = x.on_b(|a| a.outputs())[0].clone();
+        assert!(gx.is_f32());
+        let d = |v: f32| 3.0 * (1.0 - v.tanh().powi(2));
+        let gx = gx.to_f32().to_vec();
+        assert!((gx[0] - d(0.5)).abs() < 1e-6 && (gx[1] - d(-1.0)).abs() < 1e-6);
+        let gw = w.on_b(|a| a.outputs())[0].to_f64().to_vec();
+        assert!((gw[0] - (0.5f32.tanh() + (-1.0f32).tanh()) as f64).abs() < 1e-6);
+        assert_eq!(m.name(), "mul");
+    }
+}
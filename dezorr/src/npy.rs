@@ -0,0 +1,590 @@
+//! Reading and writing NumPy's `.npy` and `.npz` files.
+//!
+//! Arrays are little-endian `<f4` or `<f8` in C order; either dtype can be read
+//! into `f32` or `f64` tensors. `.npz` archives may be stored or deflated, as
+//! written by `np.savez` and `np.savez_compressed`; this module writes stored ones.
+use {
+    crate::{
+        tensor::Tensor,
+        types::{ContinuousDomain, Float},
+    },
+    std::{
+        fs::File,
+        io::{self, BufReader, BufWriter, Read, Write},
+        path::Path,
+    },
+};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+pub trait NpyElement: ContinuousDomain + Float {
+    const DESCR: &'static str;
+    fn write_le(&self, out: &mut Vec<u8>);
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for f64 {
+    const DESCR: &'static str = "<f8";
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// encode a tensor as the bytes of a version 1.0 `.npy` file
+pub fn to_npy_bytes<T: NpyElement>(tensor: &Tensor<T>) -> Vec<u8> {
+    let shape = match tensor.shape() {
+        [n] => format!("({n},)"),
+        dims => format!(
+            "({})",
+            dims.iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        T::DESCR
+    );
+    // pad so that the data starts on a 64-byte boundary
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    let mut out = Vec::with_capacity(MAGIC.len() + 4 + header.len() + 8 * tensor.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for x in tensor.iter() {
+        x.write_le(&mut out);
+    }
+    out
+}
+
+/// decode the bytes of a `.npy` file
+pub fn from_npy_bytes<T: NpyElement>(bytes: &[u8]) -> io::Result<Tensor<T>> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err(invalid("not a .npy file"));
+    }
+    let (header_len, start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if 12 <= bytes.len() => (
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+            12,
+        ),
+        v => return Err(invalid(format!("unsupported .npy version {v}"))),
+    };
+    let header = bytes
+        .get(start..start + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| invalid("broken .npy header"))?;
+    let field = |key: &str| -> io::Result<&str> {
+        let key = format!("'{key}':");
+        let at = header
+            .find(&key)
+            .ok_or_else(|| invalid(format!("no {key} in .npy header")))?;
+        Ok(header[at + key.len()..].trim_start())
+    };
+    let descr = field("descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|d| d.split('\'').next())
+        .ok_or_else(|| invalid("broken descr in .npy header"))?;
+    if field("fortran_order")?.starts_with("True") {
+        return Err(invalid("Fortran-ordered arrays are not supported"));
+    }
+    let shape = field("shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| invalid("broken shape in .npy header"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid("broken shape in .npy header"))?;
+    let body = &bytes[start + header_len..];
+    let len = shape.iter().product::<usize>();
+    let data = match descr {
+        "<f4" if body.len() == 4 * len => body
+            .chunks_exact(4)
+            .map(|b| T::from_f64(f32::from_le_bytes(b.try_into().unwrap()) as f64))
+            .collect(),
+        "<f8" if body.len() == 8 * len => body
+            .chunks_exact(8)
+            .map(|b| T::from_f64(f64::from_le_bytes(b.try_into().unwrap())))
+            .collect(),
+        "<f4" | "<f8" => return Err(invalid("the .npy data does not match its shape")),
+        _ => return Err(invalid(format!("unsupported dtype {descr}"))),
+    };
+    Ok(Tensor::new(shape, data))
+}
+
+pub fn write_npy<T: NpyElement>(w: &mut impl Write, tensor: &Tensor<T>) -> io::Result<()> {
+    w.write_all(&to_npy_bytes(tensor))
+}
+
+pub fn read_npy<T: NpyElement>(r: &mut impl Read) -> io::Result<Tensor<T>> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    from_npy_bytes(&bytes)
+}
+
+pub fn save_npy<T: NpyElement>(path: impl AsRef<Path>, tensor: &Tensor<T>) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_npy(&mut w, tensor)?;
+    w.flush()
+}
+
+pub fn load_npy<T: NpyElement>(path: impl AsRef<Path>) -> io::Result<Tensor<T>> {
+    read_npy(&mut BufReader::new(File::open(path)?))
+}
+
+/// write named arrays as an uncompressed `.npz` archive, like `np.savez`
+pub fn write_npz<T: NpyElement>(
+    w: &mut impl Write,
+    arrays: &[(&str, &Tensor<T>)],
+) -> io::Result<()> {
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for (name, tensor) in arrays.iter() {
+        let name = format!("{name}.npy");
+        let data = to_npy_bytes(tensor);
+        let (crc, size) = (crc32(&data), data.len() as u32);
+        let offset = out.len() as u32;
+        // local file header
+        out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        put_entry_fields(&mut out, crc, size, &name);
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&data);
+        // central directory entry
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        put_entry_fields(&mut directory, crc, size, &name);
+        for field in [0u16, 0, 0, 0] {
+            directory.extend_from_slice(&field.to_le_bytes());
+        }
+        directory.extend_from_slice(&0u32.to_le_bytes());
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let directory_offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    w.write_all(&out)
+}
+
+/// the fields shared by local headers and central directory entries, up to the extra length
+fn put_entry_fields(out: &mut Vec<u8>, crc: u32, size: u32, name: &str) {
+    // version 2.0, no flags, stored, 1980-01-01 00:00
+    for field in [20u16, 0, 0, 0, 0x21] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(&crc.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+}
+
+/// read all arrays of a `.npz` archive in archive order; names lose their `.npy` suffix
+pub fn read_npz<T: NpyElement>(r: &mut impl Read) -> io::Result<Vec<(String, Tensor<T>)>> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    let u16_at = |i: usize| -> io::Result<usize> {
+        bytes
+            .get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| invalid("truncated .npz archive"))
+    };
+    let u32_at = |i: usize| -> io::Result<usize> {
+        bytes
+            .get(i..i + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("truncated .npz archive"))
+    };
+    let u64_at = |i: usize| -> io::Result<usize> {
+        bytes
+            .get(i..i + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("truncated .npz archive"))
+    };
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|i| bytes[*i..*i + 4] == 0x06054b50u32.to_le_bytes())
+        .ok_or_else(|| invalid("not a .npz archive"))?;
+    let entries = u16_at(end + 10)?;
+    let mut at = u32_at(end + 16)?;
+    let mut result = Vec::with_capacity(entries);
+    for _ in 0..entries {
+        if u32_at(at)? != 0x02014b50 {
+            return Err(invalid("broken .npz central directory"));
+        }
+        let method = u16_at(at + 10)?;
+        let mut compressed = u32_at(at + 20)?;
+        let (name_len, extra_len, comment_len) =
+            (u16_at(at + 28)?, u16_at(at + 30)?, u16_at(at + 32)?);
+        let mut offset = u32_at(at + 42)?;
+        let name = String::from_utf8_lossy(
+            bytes
+                .get(at + 46..at + 46 + name_len)
+                .ok_or_else(|| invalid("truncated .npz archive"))?,
+        )
+        .to_string();
+        // the zip64 extra field holds the values saturated above, in this order
+        let mut extra = at + 46 + name_len;
+        while extra + 4 <= at + 46 + name_len + extra_len {
+            let (id, len) = (u16_at(extra)?, u16_at(extra + 2)?);
+            if id == 0x0001 {
+                let mut field = extra + 4;
+                if u32_at(at + 24)? == 0xFFFF_FFFF {
+                    field += 8;
+                }
+                if compressed == 0xFFFF_FFFF {
+                    compressed = u64_at(field)?;
+                    field += 8;
+                }
+                if offset == 0xFFFF_FFFF {
+                    offset = u64_at(field)?;
+                }
+            }
+            extra += 4 + len;
+        }
+        let start = offset + 30 + u16_at(offset + 26)? + u16_at(offset + 28)?;
+        let raw = bytes
+            .get(start..start + compressed)
+            .ok_or_else(|| invalid("truncated .npz archive"))?;
+        let data = match method {
+            0 => raw.to_vec(),
+            8 => inflate(raw)?,
+            m => return Err(invalid(format!("unsupported compression method {m}"))),
+        };
+        if crc32(&data) != u32_at(at + 16)? as u32 {
+            return Err(invalid(format!("checksum mismatch in {name}")));
+        }
+        let name = name.strip_suffix(".npy").unwrap_or(&name).to_string();
+        result.push((name, from_npy_bytes(&data)?));
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(result)
+}
+
+pub fn save_npz<T: NpyElement>(
+    path: impl AsRef<Path>,
+    arrays: &[(&str, &Tensor<T>)],
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_npz(&mut w, arrays)?;
+    w.flush()
+}
+
+pub fn load_npz<T: NpyElement>(path: impl AsRef<Path>) -> io::Result<Vec<(String, Tensor<T>)>> {
+    read_npz(&mut BufReader::new(File::open(path)?))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let table = (0..256u32)
+        .map(|n| {
+            (0..8).fold(n, |c, _| {
+                if c & 1 == 1 {
+                    0xEDB88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                }
+            })
+        })
+        .collect::<Vec<u32>>();
+    !data.iter().fold(!0u32, |c, b| {
+        table[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8)
+    })
+}
+
+/// an LSB-first bit reader over a raw deflate stream
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+/// a canonical Huffman code as the number of codes per length and the symbols in code order
+struct Huffman {
+    count: [u16; 16],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut count = [0u16; 16];
+        for l in lengths.iter() {
+            count[*l as usize] += 1;
+        }
+        count[0] = 0;
+        let mut offset = [0u16; 16];
+        for l in 1..15 {
+            offset[l + 1] = offset[l] + count[l];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (s, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbol[offset[*l as usize] as usize] = s as u16;
+                offset[*l as usize] += 1;
+            }
+        }
+        Huffman { count, symbol }
+    }
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("truncated deflate stream"))?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let bits = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer = ((self.buffer as u64) >> n) as u32;
+        self.count -= n;
+        Ok(bits)
+    }
+    fn decode(&mut self, h: &Huffman) -> io::Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= self.take(1)? as i32;
+            let count = h.count[len] as i32;
+            if code - count < first {
+                return Ok(h.symbol[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("broken Huffman code in deflate stream"))
+    }
+}
+
+/// decompress a raw deflate stream (RFC 1951)
+fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    const LBASE: [usize; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LEXT: [u32; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DBASE: [usize; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DEXT: [u32; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last = bits.take(1)? == 1;
+        let (lit, dist) = match bits.take(2)? {
+            0 => {
+                bits.buffer = 0;
+                bits.count = 0;
+                let header = data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or_else(|| invalid("truncated deflate stream"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                bits.pos += 4;
+                out.extend_from_slice(
+                    data.get(bits.pos..bits.pos + len)
+                        .ok_or_else(|| invalid("truncated deflate stream"))?,
+                );
+                bits.pos += len;
+                if last {
+                    return Ok(out);
+                }
+                continue;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+            }
+            2 => {
+                let nlen = bits.take(5)? as usize + 257;
+                let ndist = bits.take(5)? as usize + 1;
+                let ncode = bits.take(4)? as usize + 4;
+                let mut lengths = [0u8; 19];
+                for i in ORDER.iter().take(ncode) {
+                    lengths[*i] = bits.take(3)? as u8;
+                }
+                let code = Huffman::new(&lengths);
+                let mut lengths = Vec::with_capacity(nlen + ndist);
+                while lengths.len() < nlen + ndist {
+                    let (value, repeat) = match bits.decode(&code)? {
+                        s @ 0..=15 => (s as u8, 1),
+                        16 => (
+                            *lengths
+                                .last()
+                                .ok_or_else(|| invalid("broken deflate code lengths"))?,
+                            3 + bits.take(2)?,
+                        ),
+                        17 => (0, 3 + bits.take(3)?),
+                        _ => (0, 11 + bits.take(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                }
+                if nlen + ndist < lengths.len() {
+                    return Err(invalid("broken deflate code lengths"));
+                }
+                (
+                    Huffman::new(&lengths[..nlen]),
+                    Huffman::new(&lengths[nlen..]),
+                )
+            }
+            _ => return Err(invalid("invalid deflate block type")),
+        };
+        loop {
+            match bits.decode(&lit)? {
+                s @ 0..=255 => out.push(s as u8),
+                256 => break,
+                s => {
+                    let s = s - 257;
+                    if LBASE.len() <= s {
+                        return Err(invalid("broken deflate stream"));
+                    }
+                    let len = LBASE[s] + bits.take(LEXT[s])? as usize;
+                    let d = bits.decode(&dist)?;
+                    if DBASE.len() <= d {
+                        return Err(invalid("broken deflate stream"));
+                    }
+                    let back = DBASE[d] + bits.take(DEXT[d])? as usize;
+                    if out.len() < back {
+                        return Err(invalid("deflate distance is too far back"));
+                    }
+                    let from = out.len() - back;
+                    for i in 0..len {
+                        out.push(out[from + i]);
+                    }
+                }
+            }
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_npy_roundtrip() {
+        let t = Tensor::new(vec![2, 3], vec![1.0f64, -2.5, 3.0, 0.0, 1e-9, 6.0]);
+        let bytes = to_npy_bytes(&t);
+        assert_eq!(&bytes[..6], MAGIC);
+        assert_eq!((bytes[8] as usize + 10) % 64, 0);
+        assert_eq!(from_npy_bytes::<f64>(&bytes).unwrap(), t);
+        let narrow = from_npy_bytes::<f32>(&bytes).unwrap();
+        assert_eq!(narrow.shape(), &[2, 3]);
+        assert_eq!(narrow.to_vec()[1], -2.5f32);
+        let s = Tensor::scalar(4.0f32);
+        assert_eq!(from_npy_bytes::<f32>(&to_npy_bytes(&s)).unwrap(), s);
+        assert!(from_npy_bytes::<f64>(&bytes[..bytes.len() - 1]).is_err());
+    }
+    #[test]
+    fn test_npz_roundtrip() {
+        let x = Tensor::vector(vec![1.0f32, 2.0, 3.0]);
+        let gx = Tensor::new(vec![1, 2], vec![0.5f32, 0.25]);
+        let mut bytes = Vec::new();
+        write_npz(&mut bytes, &[("x", &x), ("grad_x", &gx)]).unwrap();
+        let arrays = read_npz::<f32>(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            arrays,
+            vec![("x".to_string(), x), ("grad_x".to_string(), gx)]
+        );
+    }
+    #[test]
+    fn test_npz_graph_values() {
+        use crate::{
+            func::{Function, FunctionOn},
+            DFN, TERMINAL, VARIABLE,
+        };
+        let input = to_npy_bytes(&Tensor::vector(vec![1.0f64, 2.0]));
+        let x: Function<Tensor<f64>> = VARIABLE!(from_npy_bytes(&input).unwrap());
+        let f: Function<Tensor<f64>> = Function::new(
+            DFN!(|x: Tensor<f64>| x.clone() * x),
+            DFN!(|x: Tensor<f64>| x.clone() + x),
+        );
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::vector(vec![1.0, 1.0]));
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        let (value, grad) = (y.on_f(|a| a.outputs()), x.on_b(|a| a.outputs()));
+        let mut bytes = Vec::new();
+        write_npz(&mut bytes, &[("y", &value[0]), ("grad_x", &grad[0])]).unwrap();
+        let arrays = read_npz::<f64>(&mut bytes.as_slice()).unwrap();
+        assert_eq!(arrays[0].1.to_vec(), vec![1.0, 4.0]);
+        assert_eq!(arrays[1].1.to_vec(), vec![2.0, 4.0]);
+    }
+    #[test]
+    fn test_inflate() {
+        // fixed Huffman codes: zlib.compressobj(9, zlib.DEFLATED, -15) on b"abcabcabcabc dezorr dezorr"
+        let fixed = [
+            0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0x94, 0xd4, 0xaa, 0xfc, 0xa2, 0x22, 0x28,
+            0x05, 0x00,
+        ];
+        assert_eq!(inflate(&fixed).unwrap(), b"abcabcabcabc dezorr dezorr");
+        // a match whose length has extra bits: the same on b"{}" + b" " * 60 + b"\n"
+        let padded = [0xab, 0xae, 0x55, 0xa0, 0x00, 0x70, 0x01, 0x00];
+        assert_eq!(
+            inflate(&padded).unwrap(),
+            format!("{{}}{}\n", " ".repeat(60)).as_bytes()
+        );
+        // dynamic Huffman codes: the same on b"0*0=0;1*1=1;...;23*23=529;"
+        let dynamic = [
+            0x0d, 0xce, 0xc1, 0x11, 0x00, 0x31, 0x08, 0x02, 0xc0, 0x7e, 0x78, 0x89, 0x31, 0x5e,
+            0x1c, 0x87, 0xfe, 0xdb, 0x3a, 0xbe, 0x8c, 0x2e, 0x04, 0x42, 0xb1, 0x04, 0xc5, 0x4d,
+            0xa4, 0x6a, 0x0f, 0x8e, 0x66, 0x0b, 0x25, 0xf6, 0x5e, 0x5c, 0xe5, 0xdd, 0x46, 0xeb,
+            0xf4, 0x7e, 0xf8, 0x54, 0xb3, 0x0f, 0x4f, 0x5d, 0x3b, 0x18, 0x3d, 0x2e, 0x03, 0x0c,
+            0x31, 0xac, 0x98, 0xb1, 0x93, 0xce, 0x12, 0x4c, 0xb1, 0x6a, 0x79, 0xc0, 0x63, 0x69,
+            0x96, 0x05, 0xda, 0x9c, 0x5e, 0x5e, 0xd0, 0xac, 0x5d, 0x36, 0xd8, 0x2e, 0x70, 0xf6,
+            0x81, 0x9f, 0xf2, 0xf9, 0xee, 0x81, 0x4f, 0x27, 0xfd, 0x3b, 0xe0, 0xb8, 0xd7, 0xcb,
+            0x02, 0x19, 0x2a, 0x77, 0x24, 0x91, 0x54, 0x95, 0x33, 0xcf, 0xf5, 0xde, 0x57, 0x9b,
+            0x07, 0x79, 0x74, 0x73, 0xf6, 0x07,
+        ];
+        let expected = (0..24)
+            .map(|i| format!("{i}*{i}={};", i * i))
+            .collect::<String>();
+        assert_eq!(inflate(&dynamic).unwrap(), expected.as_bytes());
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+}
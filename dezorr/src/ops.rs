@@ -0,0 +1,571 @@
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        infer::ShapeRule,
+        kernels::{zip_kernel, SimdFloat},
+        tensor::{broadcast_shapes, chunk_sizes, Index, Tensor},
+        types::ContinuousDomain,
+        TFN,
+    },
+    std::rc::Rc,
+};
+
+/// `x[index]`: the backward scatters the gradient into zeros of the input shape
+pub fn get_item<'a, T: ContinuousDomain>(index: Vec<Index>) -> Function<'a, Tensor<T>> {
+    let index = Rc::new(index);
+    let index_b = index.clone();
+    let index_s = index.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs.iter().map(|x| x.get_item(&index)).collect()),
+        TFN!(move |xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| Tensor::zeros(x.shape()).index_add(&index_b, gy))
+            .collect()),
+    )
+    .named("get_item")
+    .with_shape_rule(each(move |x| {
+        if x.len() < index_s.len() {
+            return Err(format!("too many indices for a tensor of shape {x:?}"));
+        }
+        let mut shape = Vec::new();
+        for (axis, n) in x.iter().enumerate() {
+            match index_s.get(axis) {
+                Some(i) => shape.extend(i.selected_len(*n)?),
+                None => shape.push(*n),
+            }
+        }
+        Ok(shape)
+    }))
+}
+
+/// one element per position off `axis`, at `indices` along it, as in
+/// `logits[i, labels[i]]`; the backward scatters the gradient into zeros of
+/// the input shape
+pub fn gather<'a, T: ContinuousDomain>(
+    indices: Tensor<usize>,
+    axis: usize,
+) -> Function<'a, Tensor<T>> {
+    let indices = Rc::new(indices);
+    let indices_b = indices.clone();
+    let indices_s = indices.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs
+            .iter()
+            .map(|x| x.take_along_axis(&indices, axis))
+            .collect()),
+        TFN!(move |xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| Tensor::zeros(x.shape()).add_along_axis(&indices_b, axis, gy))
+            .collect()),
+    )
+    .named("gather")
+    .with_shape_rule(each(move |x| {
+        check_axis(x, axis)?;
+        let shape = indices_s.shape();
+        if shape.len() != x.len() || (0..x.len()).any(|a| a != axis && shape[a] != x[a]) {
+            return Err(format!(
+                "indices of shape {shape:?} do not match {x:?} off axis {axis}"
+            ));
+        }
+        match indices_s.iter().find(|i| x[axis] <= **i) {
+            Some(i) => Err(format!("index {i} is out of bounds for length {}", x[axis])),
+            None => Ok(shape.to_vec()),
+        }
+    }))
+}
+
+/// join all inputs along `axis` into one output
+pub fn concat<'a, T: ContinuousDomain>(axis: usize) -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| vec![Tensor::concat(xs, axis)]),
+        TFN!(move |xs: &[Tensor<T>], gys: &[Tensor<T>]| gys[0].split(
+            &xs.iter().map(|x| x.shape()[axis]).collect::<Vec<_>>(),
+            axis
+        )),
+    )
+    .named("concat")
+    .with_shape_rule(Box::new(move |xs: &[Vec<usize>]| {
+        let first = xs.first().ok_or("nothing to concatenate")?;
+        check_axis(first, axis)?;
+        let mut shape = first.clone();
+        shape[axis] = 0;
+        for x in xs.iter() {
+            if x.len() != first.len() || (0..first.len()).any(|i| i != axis && x[i] != first[i]) {
+                return Err(format!(
+                    "shapes {first:?} and {x:?} cannot be concatenated on axis {axis}"
+                ));
+            }
+            shape[axis] += x[axis];
+        }
+        Ok(vec![shape])
+    }))
+}
+
+/// join all inputs along a new `axis` into one output
+pub fn stack<'a, T: ContinuousDomain>(axis: usize) -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| vec![Tensor::stack(xs, axis)]),
+        TFN!(move |_: &[Tensor<T>], gys: &[Tensor<T>]| gys[0].unstack(axis)),
+    )
+    .named("stack")
+    .with_shape_rule(Box::new(move |xs: &[Vec<usize>]| {
+        let first = xs.first().ok_or("nothing to stack")?;
+        if first.len() < axis {
+            return Err(format!("axis {axis} is out of bounds for {first:?}"));
+        }
+        if let Some(x) = xs.iter().find(|x| *x != first) {
+            return Err(format!("shapes {first:?} and {x:?} cannot be stacked"));
+        }
+        let mut shape = first.clone();
+        shape.insert(axis, xs.len());
+        Ok(vec![shape])
+    }))
+}
+
+/// cut the input along `axis` into one output per size
+pub fn split<'a, T: ContinuousDomain>(sizes: Vec<usize>, axis: usize) -> Function<'a, Tensor<T>> {
+    let sizes_s = sizes.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs[0].split(&sizes, axis)),
+        TFN!(move |_: &[Tensor<T>], gys: &[Tensor<T>]| vec![Tensor::concat(gys, axis)]),
+    )
+    .named("split")
+    .with_shape_rule(Box::new(move |xs: &[Vec<usize>]| {
+        let x = single(xs)?;
+        check_axis(x, axis)?;
+        if sizes_s.iter().sum::<usize>() != x[axis] {
+            return Err(format!(
+                "sizes {sizes_s:?} do not cover axis {axis} of {x:?}"
+            ));
+        }
+        Ok(pieces(x, &sizes_s, axis))
+    }))
+}
+
+/// cut the input along `axis` into `chunks` outputs of (nearly) equal size,
+/// like `np.array_split`; when the axis is too short the last outputs are empty
+pub fn chunk<'a, T: ContinuousDomain>(chunks: usize, axis: usize) -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs[0].chunk(chunks, axis)),
+        TFN!(move |_: &[Tensor<T>], gys: &[Tensor<T>]| vec![Tensor::concat(gys, axis)]),
+    )
+    .named("chunk")
+    .with_shape_rule(Box::new(move |xs: &[Vec<usize>]| {
+        let x = single(xs)?;
+        check_axis(x, axis)?;
+        if chunks == 0 {
+            return Err("the number of chunks must be positive".to_string());
+        }
+        Ok(pieces(x, &chunk_sizes(x[axis], chunks), axis))
+    }))
+}
+
+/// a view with a new shape; the gradient is reshaped back
+pub fn reshape<'a, T: ContinuousDomain>(shape: Vec<usize>) -> Function<'a, Tensor<T>> {
+    let shape_s = shape.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs.iter().map(|x| x.reshape(&shape)).collect()),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| gy.reshape(x.shape()))
+            .collect()),
+    )
+    .named("reshape")
+    .with_shape_rule(each(move |x| {
+        if x.iter().product::<usize>() != shape_s.iter().product::<usize>() {
+            return Err(format!("cannot reshape {x:?} into {shape_s:?}"));
+        }
+        Ok(shape_s.clone())
+    }))
+}
+
+/// a view with the axes reordered; the gradient is permuted back
+pub fn permute<'a, T: ContinuousDomain>(axes: Vec<usize>) -> Function<'a, Tensor<T>> {
+    // axes that are no permutation are reported by the shape rule, or by
+    // `Tensor::permute` when the graph runs
+    let mut inverse = vec![0; axes.len()];
+    for (i, a) in axes.iter().enumerate() {
+        if let Some(slot) = inverse.get_mut(*a) {
+            *slot = i;
+        }
+    }
+    let axes_s = axes.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs.iter().map(|x| x.permute(&axes)).collect()),
+        TFN!(move |_: &[Tensor<T>], gys: &[Tensor<T>]| gys
+            .iter()
+            .map(|gy| gy.permute(&inverse))
+            .collect()),
+    )
+    .named("permute")
+    .with_shape_rule(each(move |x| {
+        let mut seen = vec![false; x.len()];
+        for a in axes_s.iter() {
+            if x.len() <= *a || seen[*a] {
+                return Err(format!("axes {axes_s:?} are not a permutation of {x:?}"));
+            }
+            seen[*a] = true;
+        }
+        if axes_s.len() != x.len() {
+            return Err(format!("axes {axes_s:?} do not match {x:?}"));
+        }
+        Ok(axes_s.iter().map(|a| x[*a]).collect())
+    }))
+}
+
+/// a broadcast view; the gradient is summed over the broadcast axes
+pub fn broadcast_to<'a, T: ContinuousDomain>(shape: Vec<usize>) -> Function<'a, Tensor<T>> {
+    let shape_s = shape.clone();
+    Function::with_vjp(
+        TFN!(move |xs: &[Tensor<T>]| xs.iter().map(|x| x.broadcast_to(&shape)).collect()),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| gy.sum_to(x.shape()))
+            .collect()),
+    )
+    .named("broadcast_to")
+    .with_shape_rule(each(move |x| match broadcast_shapes(x, &shape_s) {
+        Some(s) if s == *shape_s => Ok(s),
+        _ => Err(format!("{x:?} cannot be broadcast to {shape_s:?}")),
+    }))
+}
+
+/// the matrix product of the first input by the second
+pub fn matmul<'a, T: ContinuousDomain>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| vec![xs[0].matmul(&xs[1])]),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| vec![
+            gys[0].matmul(&xs[1].transpose()),
+            xs[0].transpose().matmul(&gys[0])
+        ]),
+    )
+    .named("matmul")
+    .with_shape_rule(Box::new(|xs: &[Vec<usize>]| match xs {
+        [a, b] if a.len() == 2 && b.len() == 2 && a[1] == b[0] => Ok(vec![vec![a[0], b[1]]]),
+        [a, b] => Err(format!("shapes {a:?} and {b:?} cannot be multiplied")),
+        _ => Err(format!("{} inputs where 2 are expected", xs.len())),
+    }))
+}
+
+/// elementwise `exp`; the backward runs the kernel again, as a vjp only
+/// sees the inputs
+pub fn exp<'a, T: SimdFloat>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| xs.iter().map(|x| x.exp()).collect()),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| zip_kernel(gy, &x.exp(), T::exp_backward))
+            .collect()),
+    )
+    .named("exp")
+}
+
+pub fn tanh<'a, T: SimdFloat>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| xs.iter().map(|x| x.tanh()).collect()),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| zip_kernel(gy, &x.tanh(), T::tanh_backward))
+            .collect()),
+    )
+    .named("tanh")
+}
+
+pub fn sigmoid<'a, T: SimdFloat>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| xs.iter().map(|x| x.sigmoid()).collect()),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| xs
+            .iter()
+            .zip(gys.iter())
+            .map(|(x, gy)| zip_kernel(gy, &x.sigmoid(), T::sigmoid_backward))
+            .collect()),
+    )
+    .named("sigmoid")
+}
+
+/// the sum of two inputs, broadcast to a common shape
+pub fn add<'a, T: SimdFloat>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| vec![zip_kernel(&xs[0], &xs[1], T::add_slice)]),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| vec![
+            gys[0].sum_to(xs[0].shape()),
+            gys[0].sum_to(xs[1].shape())
+        ]),
+    )
+    .named("add")
+    .with_shape_rule(Box::new(broadcast))
+}
+
+/// the product of two inputs, broadcast to a common shape
+pub fn mul<'a, T: SimdFloat>() -> Function<'a, Tensor<T>> {
+    Function::with_vjp(
+        TFN!(|xs: &[Tensor<T>]| vec![zip_kernel(&xs[0], &xs[1], T::mul_slice)]),
+        TFN!(|xs: &[Tensor<T>], gys: &[Tensor<T>]| vec![
+            zip_kernel(&gys[0], &xs[1], T::mul_slice).sum_to(xs[0].shape()),
+            zip_kernel(&gys[0], &xs[0], T::mul_slice).sum_to(xs[1].shape())
+        ]),
+    )
+    .named("mul")
+    .with_shape_rule(Box::new(broadcast))
+}
+
+/// a shape rule applying `f` to every input on its own
+fn each(f: impl Fn(&Vec<usize>) -> Result<Vec<usize>, String> + 'static) -> ShapeRule {
+    Box::new(move |xs: &[Vec<usize>]| xs.iter().map(&f).collect())
+}
+
+fn single(xs: &[Vec<usize>]) -> Result<&Vec<usize>, String> {
+    match xs {
+        [x] => Ok(x),
+        _ => Err(format!("{} inputs where 1 is expected", xs.len())),
+    }
+}
+
+fn check_axis(x: &[usize], axis: usize) -> Result<(), String> {
+    (axis < x.len())
+        .then_some(())
+        .ok_or_else(|| format!("axis {axis} is out of bounds for {x:?}"))
+}
+
+/// the shapes of `x` cut along `axis` into the given sizes
+fn pieces(x: &[usize], sizes: &[usize], axis: usize) -> Vec<Vec<usize>> {
+    sizes
+        .iter()
+        .map(|n| {
+            let mut shape = x.to_vec();
+            shape[axis] = *n;
+            shape
+        })
+        .collect()
+}
+
+fn broadcast(xs: &[Vec<usize>]) -> Result<Vec<Vec<usize>>, String> {
+    match xs {
+        [a, b] => broadcast_shapes(a, b)
+            .map(|s| vec![s])
+            .ok_or_else(|| format!("shapes {a:?} and {b:?} cannot be broadcast")),
+        _ => Err(format!("{} inputs where 2 are expected", xs.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TERMINAL, VARIABLE};
+    #[test]
+    fn test_get_item_backward() {
+        let x: Function<Tensor<f64>> =
+            VARIABLE!(Tensor::new(vec![2, 3], vec![0.1, 0.2, 0.3, 1.1, 1.2, 1.3]));
+        let f = get_item(vec![Index::Take(vec![0, 0, 1]), Index::At(2)]);
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::vector(vec![1.0, 10.0, 100.0]));
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(
+            y.on_f(|a| a.outputs()),
+            vec![Tensor::vector(vec![0.3, 0.3, 1.3])]
+        );
+        assert_eq!(
+            x.on_b(|a| a.outputs()),
+            vec![Tensor::new(
+                vec![2, 3],
+                vec![0.0, 0.0, 11.0, 0.0, 0.0, 100.0]
+            )]
+        );
+    }
+    #[test]
+    fn test_gather_backward() {
+        // the logit of the label in each row
+        let logits: Function<Tensor<f64>> =
+            VARIABLE!(Tensor::new(vec![2, 3], vec![0.1, 0.2, 0.3, 1.1, 1.2, 1.3]));
+        let labels = Tensor::new(vec![2, 1], vec![2, 0]);
+        let f = gather(labels, 1);
+        assert_eq!(f.infer_shape(&[vec![2, 3]]), Ok(vec![vec![2, 1]]));
+        assert!(f.infer_shape(&[vec![2, 2]]).is_err());
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::new(vec![2, 1], vec![1.0, 10.0]));
+        logits.followed_by(&f).followed_by(&y);
+        logits.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(
+            y.on_f(|a| a.outputs()),
+            vec![Tensor::new(vec![2, 1], vec![0.3, 1.1])]
+        );
+        assert_eq!(
+            logits.on_b(|a| a.outputs()),
+            vec![Tensor::new(vec![2, 3], vec![0.0, 0.0, 1.0, 10.0, 0.0, 0.0])]
+        );
+    }
+    #[test]
+    fn test_concat_split_backward() {
+        let a: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![1.0, 2.0]));
+        let b: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![3.0]));
+        let cat = concat(0);
+        let cut = chunk(2, 0);
+        let y0: Function<Tensor<f64>> = TERMINAL!(Tensor::vector(vec![1.0, 10.0]));
+        let y1: Function<Tensor<f64>> = TERMINAL!(Tensor::vector(vec![100.0]));
+        a.link_to(&cat);
+        b.link_to(&cat);
+        cat.link_to(&cut);
+        cut.link_to(&y0);
+        cut.link_to(&y1);
+        a.propagate_forward();
+        b.propagate_forward();
+        assert_eq!(
+            y0.on_f(|a| a.outputs()),
+            vec![Tensor::vector(vec![1.0, 2.0])]
+        );
+        assert_eq!(y1.on_f(|a| a.outputs()), vec![Tensor::vector(vec![3.0])]);
+        Function::backward_from(&[
+            (&y0, y0.on_b(|a| a.outputs())),
+            (&y1, y1.on_b(|a| a.outputs())),
+        ]);
+        assert_eq!(
+            a.on_b(|a| a.outputs()),
+            vec![Tensor::vector(vec![1.0, 10.0])]
+        );
+        assert_eq!(b.on_b(|a| a.outputs()), vec![Tensor::vector(vec![100.0])]);
+    }
+    #[test]
+    fn test_chunk_short_axis() {
+        // 3 elements into 4 chunks: the last output is empty
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![1.0, 2.0, 3.0]));
+        let cut = chunk(4, 0);
+        assert_eq!(
+            cut.infer_shape(&[vec![3]]),
+            Ok(vec![vec![1], vec![1], vec![1], vec![0]])
+        );
+        let ys: Vec<Function<Tensor<f64>>> = (0..4)
+            .map(|i| TERMINAL!(Tensor::filled(&[usize::from(i < 3)], 10.0)))
+            .collect();
+        x.link_to(&cut);
+        for y in ys.iter() {
+            cut.link_to(y);
+        }
+        x.propagate_forward();
+        assert_eq!(ys[3].on_f(|a| a.outputs()), vec![Tensor::zeros(&[0])]);
+        Function::backward_from(
+            &ys.iter()
+                .map(|y| (y, y.on_b(|a| a.outputs())))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(x.on_b(|a| a.outputs()), vec![Tensor::filled(&[3], 10.0)]);
+    }
+    #[test]
+    fn test_stack_backward() {
+        let a: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![1.0, 2.0]));
+        let b: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![3.0, 4.0]));
+        let s = stack(1);
+        let parts = split(vec![1, 1], 0);
+        let y0: Function<Tensor<f64>> = TERMINAL!(Tensor::new(vec![1, 2], vec![1.0, 2.0]));
+        let y1: Function<Tensor<f64>> = TERMINAL!(Tensor::new(vec![1, 2], vec![3.0, 4.0]));
+        a.link_to(&s);
+        b.link_to(&s);
+        s.link_to(&parts);
+        parts.link_to(&y0);
+        parts.link_to(&y1);
+        a.propagate_forward();
+        b.propagate_forward();
+        assert_eq!(
+            y1.on_f(|a| a.outputs()),
+            vec![Tensor::new(vec![1, 2], vec![2.0, 4.0])]
+        );
+        Function::backward_from(&[
+            (&y0, y0.on_b(|a| a.outputs())),
+            (&y1, y1.on_b(|a| a.outputs())),
+        ]);
+        assert_eq!(
+            a.on_b(|a| a.outputs()),
+            vec![Tensor::vector(vec![1.0, 3.0])]
+        );
+        assert_eq!(
+            b.on_b(|a| a.outputs()),
+            vec![Tensor::vector(vec![2.0, 4.0])]
+        );
+    }
+    #[test]
+    fn test_layout_views() {
+        let x: Function<Tensor<f64>> =
+            VARIABLE!(Tensor::new(vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let t = permute(vec![1, 0]);
+        let r = reshape(vec![6]);
+        let y: Function<Tensor<f64>> =
+            TERMINAL!(Tensor::vector(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        x.followed_by(&t).followed_by(&r).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert!(t.on_f(|a| a.outputs()[0].shares_storage(&x.on_f(|b| b.outputs()[0].clone()))));
+        assert_eq!(
+            y.on_f(|a| a.outputs())[0].to_vec(),
+            vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]
+        );
+        assert_eq!(
+            x.on_b(|a| a.outputs())[0].to_vec(),
+            vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0]
+        );
+    }
+    #[test]
+    fn test_permute_shape_rule() {
+        let shape = |axes: Vec<usize>| permute::<f64>(axes).infer_shape(&[vec![2, 3, 5]]);
+        assert_eq!(shape(vec![2, 0, 1]), Ok(vec![vec![5, 2, 3]]));
+        assert_eq!(
+            shape(vec![0, 3, 1]),
+            Err("axes [0, 3, 1] are not a permutation of [2, 3, 5]".to_string())
+        );
+        assert!(shape(vec![0, 0, 1]).is_err());
+        assert!(shape(vec![1, 0]).is_err());
+    }
+    #[test]
+    fn test_broadcast_backward() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![1.0, 2.0]));
+        let b = broadcast_to(vec![3, 2]);
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::filled(&[3, 2], 1.0));
+        x.followed_by(&b).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(
+            x.on_b(|a| a.outputs()),
+            vec![Tensor::vector(vec![3.0, 3.0])]
+        );
+    }
+    #[test]
+    fn test_backward_with_ones() {
+        // the default seed is shaped like the terminal
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::new(vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]));
+        let p = permute(vec![1, 0]);
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::zeros(&[2, 2]));
+        x.followed_by(&p).followed_by(&y);
+        x.propagate_forward();
+        y.backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![Tensor::filled(&[2, 2], 1.0)]);
+        assert_eq!(y.on_b(|a| a.outputs()), vec![Tensor::zeros(&[2, 2])]);
+        let f = reshape::<f64>(vec![4]);
+        let x0 = Tensor::vector(vec![1.0, 2.0]).broadcast_to(&[2, 2]);
+        assert_eq!(f.grad_at(&[x0]), vec![Tensor::filled(&[2, 2], 1.0)]);
+    }
+    #[test]
+    fn test_kernel_ops_backward() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor::vector(vec![0.5, -1.0]));
+        let w: Function<Tensor<f64>> = VARIABLE!(Tensor::scalar(3.0));
+        let m = mul();
+        let t = tanh();
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor::vector(vec![1.0, 1.0]));
+        x.link_to(&m);
+        w.link_to(&m);
+        m.followed_by(&t).followed_by(&y);
+        x.propagate_forward();
+        w.propagate_forward();
+        y.propagate_backward();
+        let d = |v: f64| 1.0 - (3.0 * v).tanh().powi(2);
+        let gx = x.on_b(|a| a.outputs())[0].to_vec();
+        let gw = w.on_b(|a| a.outputs())[0].to_vec();
+        assert!((gx[0] - 3.0 * d(0.5)).abs() < 1e-12);
+        assert!((gx[1] - 3.0 * d(-1.0)).abs() < 1e-12);
+        assert!((gw[0] - (0.5 * d(0.5) - d(-1.0))).abs() < 1e-12);
+    }
+}
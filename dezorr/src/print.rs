@@ -0,0 +1,228 @@
+//! NumPy-like printing of tensors.
+use {
+    crate::{tensor::Tensor, types::Scalar},
+    std::fmt,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrintOptions {
+    /// digits after the decimal point; `None` prints each element as is
+    pub precision: Option<usize>,
+    /// tensors with more elements than this are truncated
+    pub threshold: usize,
+    /// elements kept at each end of a truncated axis
+    pub edge_items: usize,
+    /// print only the shape, dtype, min, max and mean
+    pub summary: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            precision: None,
+            threshold: 1000,
+            edge_items: 3,
+            summary: false,
+        }
+    }
+}
+
+/// a tensor bound to the options it is printed with
+pub struct TensorDisplay<'t, T: Scalar> {
+    tensor: &'t Tensor<T>,
+    options: PrintOptions,
+}
+
+impl<T: Scalar> Tensor<T> {
+    pub fn display(&self, options: PrintOptions) -> TensorDisplay<'_, T> {
+        TensorDisplay {
+            tensor: self,
+            options,
+        }
+    }
+    pub fn summary(&self) -> TensorDisplay<'_, T> {
+        self.display(PrintOptions {
+            summary: true,
+            ..PrintOptions::default()
+        })
+    }
+}
+
+impl<T: Scalar> fmt::Display for Tensor<T> {
+    /// a precision given in the format string, as in `{:.3}`, overrides the default
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = PrintOptions {
+            precision: f.precision(),
+            ..PrintOptions::default()
+        };
+        self.display(options).fmt(f)
+    }
+}
+
+impl<T: Scalar> fmt::Display for TensorDisplay<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = self.options.precision.or(f.precision());
+        let show = |x: f64| match precision {
+            Some(p) => format!("{x:.p$}"),
+            None => format!("{x}"),
+        };
+        let t = self.tensor.contiguous();
+        let data = t.as_slice().unwrap();
+        if self.options.summary {
+            write!(
+                f,
+                "Tensor(shape={:?}, dtype={}",
+                t.shape(),
+                std::any::type_name::<T>()
+            )?;
+            if let Some(first) = data.first() {
+                let (mut min, mut max, mut sum) = (first, first, 0.0);
+                for x in data.iter() {
+                    if x < min {
+                        min = x;
+                    }
+                    if max < x {
+                        max = x;
+                    }
+                    sum += x.to_f64();
+                }
+                write!(
+                    f,
+                    ", min={}, max={}, mean={}",
+                    show(min.to_f64()),
+                    show(max.to_f64()),
+                    show(sum / t.len() as f64)
+                )?;
+            }
+            return write!(f, ")");
+        }
+        let edge = self.options.edge_items;
+        let truncate = self.options.threshold < t.len();
+        // the positions printed on each axis, with `None` standing for `...`
+        let visible = t
+            .shape()
+            .iter()
+            .map(|n| {
+                if truncate && 2 * edge < *n {
+                    (0..edge)
+                        .map(Some)
+                        .chain(std::iter::once(None))
+                        .chain((n - edge..*n).map(Some))
+                        .collect()
+                } else {
+                    (0..*n).map(Some).collect()
+                }
+            })
+            .collect::<Vec<Vec<Option<usize>>>>();
+        let element = |x: &T| match precision {
+            Some(p) => format!("{x:.p$}"),
+            None => format!("{x}"),
+        };
+        let strides = t.strides().iter().map(|s| *s as usize).collect::<Vec<_>>();
+        let mut cells = Vec::new();
+        collect_offsets(&visible, &strides, 0, 0, &mut cells);
+        let width = cells
+            .iter()
+            .map(|o| element(&data[*o]).chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        write_axis(&mut out, &visible, &strides, 0, 0, &|o| {
+            format!("{:>width$}", element(&data[o]))
+        });
+        f.write_str(&out)
+    }
+}
+
+fn collect_offsets(
+    visible: &[Vec<Option<usize>>],
+    strides: &[usize],
+    axis: usize,
+    offset: usize,
+    out: &mut Vec<usize>,
+) {
+    if axis == visible.len() {
+        out.push(offset);
+        return;
+    }
+    for i in visible[axis].iter().flatten() {
+        collect_offsets(visible, strides, axis + 1, offset + i * strides[axis], out);
+    }
+}
+
+fn write_axis(
+    out: &mut String,
+    visible: &[Vec<Option<usize>>],
+    strides: &[usize],
+    axis: usize,
+    offset: usize,
+    cell: &dyn Fn(usize) -> String,
+) {
+    if axis == visible.len() {
+        out.push_str(&cell(offset));
+        return;
+    }
+    let separator = if axis + 1 == visible.len() {
+        " ".to_string()
+    } else {
+        format!(
+            "{}{}",
+            "\n".repeat(visible.len() - axis - 1),
+            " ".repeat(axis + 1)
+        )
+    };
+    out.push('[');
+    for (k, i) in visible[axis].iter().enumerate() {
+        if 0 < k {
+            out.push_str(&separator);
+        }
+        match i {
+            Some(i) => write_axis(
+                out,
+                visible,
+                strides,
+                axis + 1,
+                offset + i * strides[axis],
+                cell,
+            ),
+            None => out.push_str("..."),
+        }
+    }
+    out.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_print_tensor() {
+        assert_eq!(format!("{}", Tensor::scalar(2.5f64)), "2.5");
+        let t = Tensor::new(vec![2, 2], vec![1.0f64, -2.5, 30.0, 4.0]);
+        assert_eq!(format!("{t}"), "[[   1 -2.5]\n [  30    4]]");
+        assert_eq!(format!("{t:.2}"), "[[ 1.00 -2.50]\n [30.00  4.00]]");
+        let cube = Tensor::new(vec![2, 1, 2], vec![0usize, 1, 2, 3]);
+        assert_eq!(format!("{cube}"), "[[[0 1]]\n\n [[2 3]]]");
+        assert_eq!(format!("{}", Tensor::<f64>::zeros(&[0])), "[]");
+    }
+    #[test]
+    fn test_print_truncated() {
+        let t = Tensor::new(vec![4, 5], (0..20usize).collect());
+        let options = PrintOptions {
+            threshold: 10,
+            edge_items: 1,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            format!("{}", t.display(options)),
+            "[[ 0 ...  4]\n ...\n [15 ... 19]]"
+        );
+    }
+    #[test]
+    fn test_print_summary() {
+        let t = Tensor::new(vec![2, 2], vec![1.0f64, -2.0, 3.0, 6.0]);
+        assert_eq!(
+            format!("{:.1}", t.summary()),
+            "Tensor(shape=[2, 2], dtype=f64, min=-2.0, max=6.0, mean=2.0)"
+        );
+    }
+}
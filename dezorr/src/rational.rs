@@ -0,0 +1,229 @@
+//! Exact fractions, for graphs whose values and gradients must be reproduced
+//! bit for bit.
+use {
+    crate::types::{ContinuousDomain, Scalar},
+    std::{cmp::Ordering, fmt},
+};
+
+/// a fraction in lowest terms with a positive denominator; arithmetic panics
+/// rather than overflowing `i128`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+fn checked(x: Option<i128>) -> i128 {
+    x.expect("rational arithmetic overflowed i128")
+}
+
+impl Rational {
+    pub fn new(num: i128, den: i128) -> Self {
+        assert!(den != 0, "the denominator of a rational is zero");
+        let g = gcd(num, den) * den.signum();
+        Rational {
+            num: num / g,
+            den: den / g,
+        }
+    }
+    pub fn integer(n: i128) -> Self {
+        Rational { num: n, den: 1 }
+    }
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+    pub fn recip(&self) -> Self {
+        Rational::new(self.den, self.num)
+    }
+    pub fn pow(&self, n: i32) -> Self {
+        let base = if n < 0 { self.recip() } else { *self };
+        (0..n.unsigned_abs()).fold(Rational::integer(1), |p, _| p * base)
+    }
+}
+
+impl Default for Rational {
+    fn default() -> Self {
+        Rational::integer(0)
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(n: i128) -> Self {
+        Rational::integer(n)
+    }
+}
+
+impl std::ops::Add for Rational {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        let g = gcd(self.den, other.den);
+        let (a, b) = (self.den / g, other.den / g);
+        Rational::new(
+            checked(
+                checked(self.num.checked_mul(b)).checked_add(checked(other.num.checked_mul(a))),
+            ),
+            checked(self.den.checked_mul(b)),
+        )
+    }
+}
+
+impl std::ops::Neg for Rational {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Rational {
+            num: checked(self.num.checked_neg()),
+            den: self.den,
+        }
+    }
+}
+
+impl std::ops::Sub for Rational {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl std::ops::Mul for Rational {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        // cancel crosswise first to keep the products small
+        let (g1, g2) = (gcd(self.num, other.den), gcd(other.num, self.den));
+        let (g1, g2) = (g1.max(1), g2.max(1));
+        Rational::new(
+            checked((self.num / g1).checked_mul(other.num / g2)),
+            checked((self.den / g2).checked_mul(other.den / g1)),
+        )
+    }
+}
+
+impl std::ops::Div for Rational {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        std::ops::Mul::mul(self, other.recip())
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// compare by the continued fractions of both, which never overflows: equal
+/// integer parts leave the fractional parts, whose reciprocals compare the
+/// other way round
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (mut a, mut b) = ((self.num, self.den), (other.num, other.den));
+        let mut flipped = false;
+        loop {
+            let (qa, qb) = (a.0.div_euclid(a.1), b.0.div_euclid(b.1));
+            let (ra, rb) = (a.0.rem_euclid(a.1), b.0.rem_euclid(b.1));
+            let order = match (ra, rb) {
+                _ if qa != qb => qa.cmp(&qb),
+                (0, 0) => Ordering::Equal,
+                (0, _) => Ordering::Less,
+                (_, 0) => Ordering::Greater,
+                _ => {
+                    (a, b) = ((a.1, ra), (b.1, rb));
+                    flipped = !flipped;
+                    continue;
+                }
+            };
+            return if flipped { order.reverse() } else { order };
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl ContinuousDomain for Rational {}
+
+impl Scalar for Rational {
+    fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        func::{Function, FunctionOn},
+        DFN, TERMINAL, VARIABLE,
+    };
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den)
+    }
+    #[test]
+    fn test_rational_arithmetic() {
+        assert_eq!(r(2, -4), r(-1, 2));
+        assert_eq!(r(1, 3) + r(1, 6), r(1, 2));
+        assert_eq!(r(1, 3) - r(1, 2), r(-1, 6));
+        assert_eq!(r(2, 3) * r(9, 4), r(3, 2));
+        assert_eq!(r(2, 3) / r(-4, 9), r(-3, 2));
+        assert_eq!(r(2, 3).pow(-2), r(9, 4));
+        assert!(r(1, 3) < r(1, 2));
+        assert_eq!(format!("{} {}", r(6, 3), r(-1, 7)), "2 -1/7");
+        assert_eq!(Rational::default(), r(0, 5));
+    }
+    #[test]
+    fn test_rational_order() {
+        assert!(r(-1, 2) < r(-1, 3) && r(-1, 3) < r(0, 1));
+        assert!(r(5, 3) < r(7, 4) && r(7, 4) > r(12, 7));
+        assert_eq!(r(4, 6).cmp(&r(2, 3)), Ordering::Equal);
+        // 1 + 1/(m - 1) < 1 + 1/(m - 2), where the differences overflow
+        let m = i128::MAX;
+        let (a, b) = (r(m, m - 1), r(m - 1, m - 2));
+        assert!(a < b && -a > -b);
+        assert!(Rational::integer(m) > r(m, 2) && r(i128::MIN + 1, 1) < r(-m, 2));
+    }
+    #[test]
+    #[should_panic(expected = "overflowed")]
+    fn test_rational_overflow() {
+        let _ = Rational::integer(i128::MAX) + Rational::integer(1);
+    }
+    #[test]
+    fn test_exact_gradient() {
+        // f(x) = 1 / (x^2 + 1) at x = 1/2: f = 4/5, f' = -2x / (x^2 + 1)^2 = -16/25
+        let x: Function<Rational> = VARIABLE!(r(1, 2));
+        let square: Function<Rational> =
+            Function::new(DFN!(|x: Rational| x * x), DFN!(|x: Rational| x + x));
+        let shift: Function<Rational> = Function::new(
+            DFN!(|x: Rational| x + Rational::integer(1)),
+            DFN!(|_| Rational::integer(1)),
+        );
+        let invert: Function<Rational> = Function::new(
+            DFN!(|x: Rational| x.recip()),
+            DFN!(|x: Rational| -x.pow(-2)),
+        );
+        let y: Function<Rational> = TERMINAL!(Rational::integer(1));
+        x.followed_by(&square)
+            .followed_by(&shift)
+            .followed_by(&invert)
+            .followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![r(4, 5)]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![r(-16, 25)]);
+    }
+}
@@ -0,0 +1,140 @@
+//! What the backward pass keeps once it has used it.
+//!
+//! Each function lets go of the gradients it received and of the forward
+//! inputs its backward read as soon as its own backward has run, and of its
+//! own gradients unless [`Function::retain_grad`] was called on it. Variables
+//! keep their gradients and terminals their seeds. A second backward pass then
+//! needs a new forward pass, unless a [`retain_graph`] guard is alive on the
+//! current thread, which keeps the forward inputs. The forward outputs of the
+//! functions stay readable after the backward pass, unless a [`free_outputs`]
+//! guard asks for them to be dropped too, so that only the values of the
+//! variables and terminals stay alive.
+//!
+//! [`Function::retain_grad`]: crate::func::Function::retain_grad
+use std::cell::Cell;
+
+thread_local! {
+    static RETAINING: Cell<bool> = const { Cell::new(false) };
+    static FREEING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// whether backward passes on this thread keep the forward inputs
+pub fn is_retaining_graph() -> bool {
+    RETAINING.with(|r| r.get())
+}
+
+/// keep the forward inputs until the returned guard is dropped
+pub fn retain_graph() -> RetainGraph {
+    RetainGraph(RETAINING.with(|r| r.replace(true)))
+}
+
+/// restores the previous mode on drop
+pub struct RetainGraph(bool);
+
+impl Drop for RetainGraph {
+    fn drop(&mut self) {
+        RETAINING.with(|r| r.set(self.0));
+    }
+}
+
+/// whether backward passes on this thread drop the forward outputs
+pub fn is_freeing_outputs() -> bool {
+    FREEING.with(|f| f.get())
+}
+
+/// drop the forward outputs of functions once their backward has run, until
+/// the returned guard is dropped
+pub fn free_outputs() -> FreeOutputs {
+    FreeOutputs(FREEING.with(|f| f.replace(true)))
+}
+
+/// restores the previous mode on drop
+pub struct FreeOutputs(bool);
+
+impl Drop for FreeOutputs {
+    fn drop(&mut self) {
+        FREEING.with(|f| f.set(self.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        func::{Function, FunctionOn},
+        DFN, TERMINAL, VARIABLE,
+    };
+    fn chain<'a>() -> [Function<'a, f64>; 4] {
+        [
+            VARIABLE!(0.5),
+            Function::new(DFN!(|x: f64| x * x), DFN!(|x: f64| 2.0 * x)),
+            Function::new(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp())),
+            TERMINAL!(1.0),
+        ]
+    }
+    #[test]
+    fn test_intermediate_gradients_released() {
+        let [x, square, exp, y] = chain();
+        x.followed_by(&square).followed_by(&exp).followed_by(&y);
+        exp.retain_grad();
+        x.propagate_forward();
+        y.propagate_backward();
+        let g = 2.0 * 0.5 * 0.25f64.exp();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![g]);
+        assert_eq!(exp.on_b(|a| a.outputs()), vec![0.25f64.exp()]);
+        assert!(!square.on_b(|a| a.is_applied()));
+        // the lanes between the functions hold nothing
+        assert_eq!(exp.on_f(|a| a.inputs()), vec![None]);
+        assert_eq!(exp.on_b(|a| a.inputs()), vec![None]);
+        assert_eq!(square.on_f(|a| a.outputs()), vec![0.25]);
+        // the outputs of the graph and the seeds stay
+        assert_eq!(y.on_f(|a| a.outputs()), vec![0.25f64.exp()]);
+        assert_eq!(y.on_b(|a| a.outputs()), vec![1.0]);
+    }
+    #[test]
+    fn test_retain_graph() {
+        let [x, square, exp, y] = chain();
+        x.followed_by(&square).followed_by(&exp).followed_by(&y);
+        x.propagate_forward();
+        {
+            let _graph = retain_graph();
+            y.backward_with(vec![1.0]);
+            y.backward_with(vec![2.0]);
+        }
+        assert!(!is_retaining_graph());
+        assert_eq!(x.on_b(|a| a.outputs()), vec![2.0 * 0.25f64.exp()]);
+    }
+    #[test]
+    fn test_free_outputs() {
+        let [x, square, exp, y] = chain();
+        x.followed_by(&square).followed_by(&exp).followed_by(&y);
+        x.propagate_forward();
+        {
+            let _outputs = free_outputs();
+            y.propagate_backward();
+        }
+        assert!(!is_freeing_outputs());
+        assert!(square.on_f(|a| a.outputs()).is_empty());
+        assert!(exp.on_f(|a| a.outputs()).is_empty());
+        // the variables and terminals keep theirs
+        assert_eq!(x.on_f(|a| a.outputs()), vec![0.5]);
+        assert_eq!(y.on_f(|a| a.outputs()), vec![0.25f64.exp()]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![0.25f64.exp()]);
+        // a retained graph keeps the outputs for the next pass
+        x.propagate_forward();
+        {
+            let (_outputs, _graph) = (free_outputs(), retain_graph());
+            y.propagate_backward();
+        }
+        assert_eq!(square.on_f(|a| a.outputs()), vec![0.25]);
+    }
+    #[test]
+    #[should_panic(expected = "propagate forward again")]
+    fn test_backward_twice() {
+        let [x, square, exp, y] = chain();
+        x.followed_by(&square).followed_by(&exp).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        y.propagate_backward();
+    }
+}
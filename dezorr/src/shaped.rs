@@ -0,0 +1,291 @@
+//! Matrices whose shape is part of their type.
+//!
+//! [`Tensor2`] wraps a dynamic [`Tensor`], so converting between the two
+//! forms shares the storage. [`Port`] tags a node of a dynamic graph with the
+//! shape of its output. Ports start at variables whose values are checked
+//! against the declared shape, and grow only through functions they build
+//! themselves into caller-owned [`Slot`]s or whose shape rules agree, so that
+//! wiring a matrix product with mismatched inner dimensions is rejected by the
+//! compiler:
+//!
+//! ```compile_fail
+//! use dezorr::{func::{Function, FunctionOn}, shaped::{Port, Slot, Tensor2}, tensor::Tensor};
+//! let x: Function<Tensor<f64>> = Function::coterminal(vec![Tensor2::<f64, 2, 3>::zeros().into()]);
+//! let w: Function<Tensor<f64>> = Function::coterminal(vec![Tensor2::<f64, 2, 4>::zeros().into()]);
+//! let m = Slot::default();
+//! let x = Port::<f64, 2, 3>::new(&x).unwrap();
+//! let w = Port::<f64, 2, 4>::new(&w).unwrap();
+//! x.matmul(w, &m); // 3 != 2
+//! ```
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        ops,
+        tensor::Tensor,
+        types::ContinuousDomain,
+    },
+    std::{cell::OnceCell, fmt},
+};
+
+/// a dynamic tensor of a shape other than the one a static type requires
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShapeMismatch {
+    pub expected: Vec<usize>,
+    pub found: Vec<usize>,
+}
+
+impl fmt::Display for ShapeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected shape {:?}, found {:?}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ShapeMismatch {}
+
+/// an `R` x `C` matrix
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor2<T: ContinuousDomain, const R: usize, const C: usize>(Tensor<T>);
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> Tensor2<T, R, C> {
+    /// elements in row-major order
+    pub fn new(data: Vec<T>) -> Self {
+        Tensor2(Tensor::new(vec![R, C], data))
+    }
+    pub fn zeros() -> Self {
+        Tensor2(Tensor::zeros(&[R, C]))
+    }
+    pub fn filled(value: T) -> Self {
+        Tensor2(Tensor::filled(&[R, C], value))
+    }
+    pub fn as_dynamic(&self) -> &Tensor<T> {
+        &self.0
+    }
+    pub fn get(&self, row: usize, column: usize) -> &T {
+        self.0.get(&[row, column])
+    }
+    pub fn matmul<const K: usize>(&self, other: &Tensor2<T, C, K>) -> Tensor2<T, R, K> {
+        Tensor2(self.0.matmul(&other.0))
+    }
+    pub fn transpose(&self) -> Tensor2<T, C, R> {
+        Tensor2(self.0.transpose())
+    }
+}
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> Default for Tensor2<T, R, C> {
+    fn default() -> Self {
+        Tensor2::zeros()
+    }
+}
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> From<Tensor2<T, R, C>> for Tensor<T> {
+    fn from(t: Tensor2<T, R, C>) -> Self {
+        t.0
+    }
+}
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> TryFrom<Tensor<T>> for Tensor2<T, R, C> {
+    type Error = ShapeMismatch;
+    fn try_from(t: Tensor<T>) -> Result<Self, Self::Error> {
+        if t.shape() == [R, C] {
+            Ok(Tensor2(t))
+        } else {
+            Err(ShapeMismatch {
+                expected: vec![R, C],
+                found: t.shape().to_vec(),
+            })
+        }
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($trait: ident, $method: ident) => {
+        impl<T: ContinuousDomain, const R: usize, const C: usize> std::ops::$trait
+            for Tensor2<T, R, C>
+        {
+            type Output = Self;
+            fn $method(self, other: Self) -> Self::Output {
+                Tensor2(std::ops::$trait::$method(self.0, other.0))
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add);
+impl_binary_op!(Sub, sub);
+impl_binary_op!(Mul, mul);
+impl_binary_op!(Div, div);
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> ContinuousDomain for Tensor2<T, R, C> {
+    fn is_finite(&self) -> bool {
+        self.0.is_finite()
+    }
+}
+
+/// a node of a dynamic graph whose output is an `R` x `C` matrix
+pub struct Port<'a, T: ContinuousDomain, const R: usize, const C: usize>(
+    &'a Function<'a, Tensor<T>>,
+);
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> Clone for Port<'_, T, R, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ContinuousDomain, const R: usize, const C: usize> Copy for Port<'_, T, R, C> {}
+
+/// room for a function a [`Port`] builds, owned by the caller alongside the
+/// rest of the graph
+pub type Slot<'a, T> = OnceCell<Function<'a, Tensor<T>>>;
+
+impl<'a, T: ContinuousDomain, const R: usize, const C: usize> Port<'a, T, R, C> {
+    /// a port at the variable `node`, whose values must all be `R` x `C`
+    pub fn new(node: &'a Function<'a, Tensor<T>>) -> Result<Self, ShapeMismatch> {
+        assert!(node.is_coterminal(), "a port starts at a variable");
+        for value in node.on_f(|a| a.outputs()) {
+            Tensor2::<T, R, C>::try_from(value)?;
+        }
+        Ok(Port(node))
+    }
+    pub fn node(&self) -> &'a Function<'a, Tensor<T>> {
+        self.0
+    }
+    /// feed this output to `f`, whose shape rule must keep the shape, like
+    /// that of an elementwise op
+    pub fn then(self, f: &'a Function<'a, Tensor<T>>) -> Result<Self, ShapeMismatch> {
+        let found = f.infer_shape(&[vec![R, C]]).unwrap_or_default();
+        if found != [vec![R, C]] {
+            return Err(ShapeMismatch {
+                expected: vec![R, C],
+                found: found.concat(),
+            });
+        }
+        self.0.link_to(f);
+        Ok(Port(f))
+    }
+    /// the product with `other`, by a matmul built into `slot`
+    pub fn matmul<const K: usize>(
+        self,
+        other: Port<'a, T, C, K>,
+        slot: &'a Slot<'a, T>,
+    ) -> Port<'a, T, R, K> {
+        let f = fill(slot, ops::matmul());
+        self.0.link_to(f);
+        other.0.link_to(f);
+        Port(f)
+    }
+    /// the transpose, by a permute built into `slot`
+    pub fn transpose(self, slot: &'a Slot<'a, T>) -> Port<'a, T, C, R> {
+        let f = fill(slot, ops::permute(vec![1, 0]));
+        self.0.link_to(f);
+        Port(f)
+    }
+    /// the value computed at this port by the last forward propagation, or
+    /// an error if the graph computed another shape there
+    pub fn value(&self) -> Result<Option<Tensor2<T, R, C>>, ShapeMismatch> {
+        self.0
+            .on_f(|a| a.outputs().first().cloned())
+            .map(Tensor2::try_from)
+            .transpose()
+    }
+}
+
+fn fill<'a, T: ContinuousDomain>(
+    slot: &'a Slot<'a, T>,
+    f: Function<'a, Tensor<T>>,
+) -> &'a Function<'a, Tensor<T>> {
+    assert!(slot.set(f).is_ok(), "the slot holds a function already");
+    slot.get().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ops, DFN, TERMINAL, VARIABLE};
+    #[test]
+    fn test_tensor2() {
+        let a: Tensor2<f64, 2, 3> = Tensor2::new(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        let b: Tensor2<f64, 3, 2> = a.transpose();
+        let c: Tensor2<f64, 2, 2> = a.matmul(&b);
+        assert_eq!(*c.get(1, 1), 50.0);
+        assert_eq!((c.clone() + c.clone()).get(0, 1), &28.0);
+        let d: Tensor<f64> = c.into();
+        assert_eq!(d.shape(), &[2, 2]);
+        assert_eq!(
+            Tensor2::<f64, 3, 3>::try_from(d),
+            Err(ShapeMismatch {
+                expected: vec![3, 3],
+                found: vec![2, 2]
+            })
+        );
+    }
+    #[test]
+    fn test_tensor2_graph() {
+        // elementwise graphs work on the static type directly
+        let x: Function<Tensor2<f64, 1, 2>> = VARIABLE!(Tensor2::new(vec![1.0, 2.0]));
+        let f: Function<Tensor2<f64, 1, 2>> = Function::new(
+            DFN!(|x: Tensor2<f64, 1, 2>| x.clone() * x),
+            DFN!(|x: Tensor2<f64, 1, 2>| x.clone() + x),
+        );
+        let y: Function<Tensor2<f64, 1, 2>> = TERMINAL!(Tensor2::filled(1.0));
+        x.followed_by(&f).followed_by(&y);
+        x.propagate_forward();
+        y.propagate_backward();
+        assert_eq!(x.on_b(|a| a.outputs()), vec![Tensor2::new(vec![2.0, 4.0])]);
+    }
+    #[test]
+    fn test_port_matmul() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor2::<f64, 1, 2>::new(vec![1.0, 2.0]).into());
+        let w: Function<Tensor<f64>> =
+            VARIABLE!(Tensor2::<f64, 2, 3>::new(vec![1.0, 0.0, 2.0, 0.0, 1.0, 3.0]).into());
+        let m = Slot::default();
+        let t = ops::tanh();
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor2::<f64, 1, 3>::filled(1.0).into());
+        let (px, pw): (Port<f64, 1, 2>, Port<f64, 2, 3>) =
+            (Port::new(&x).unwrap(), Port::new(&w).unwrap());
+        let h = px.matmul(pw, &m);
+        let out = h.then(&t).unwrap();
+        out.node().link_to(&y);
+        x.propagate_forward();
+        w.propagate_forward();
+        y.propagate_backward();
+        let v: Tensor2<f64, 1, 3> = h.value().unwrap().unwrap();
+        assert_eq!(v, Tensor2::new(vec![1.0, 2.0, 8.0]));
+        let gw = Tensor2::<f64, 2, 3>::try_from(w.on_b(|a| a.outputs())[0].clone()).unwrap();
+        assert!((gw.get(1, 2) - 2.0 * (1.0 - 8.0f64.tanh().powi(2))).abs() < 1e-12);
+    }
+    #[test]
+    fn test_port_checks() {
+        let x: Function<Tensor<f64>> = VARIABLE!(Tensor2::<f64, 2, 3>::zeros().into());
+        assert_eq!(
+            Port::<f64, 3, 2>::new(&x).err(),
+            Some(ShapeMismatch {
+                expected: vec![3, 2],
+                found: vec![2, 3]
+            })
+        );
+        let px = Port::<f64, 2, 3>::new(&x).unwrap();
+        // a reshape does not keep the shape
+        let r = ops::reshape(vec![6]);
+        assert_eq!(
+            px.then(&r).err(),
+            Some(ShapeMismatch {
+                expected: vec![2, 3],
+                found: vec![6]
+            })
+        );
+        let t = Slot::default();
+        let pt = px.transpose(&t);
+        let y: Function<Tensor<f64>> = TERMINAL!(Tensor2::<f64, 3, 2>::zeros().into());
+        pt.node().link_to(&y);
+        x.propagate_forward();
+        assert_eq!(pt.value(), Ok(Some(Tensor2::<f64, 3, 2>::zeros())));
+        // a port declared over a node of another shape reports it
+        let wrong: Port<f64, 2, 2> = Port(t.get().unwrap());
+        assert!(wrong.value().is_err());
+    }
+}
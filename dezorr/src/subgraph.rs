@@ -0,0 +1,415 @@
+//! Copies of the part of a graph between some inputs and outputs.
+//!
+//! [`clone_subgraph`] duplicates every function the outputs depend on, up to
+//! the inputs, and links the copies among themselves in the order of the
+//! original lanes, so each copy sees its inputs and outputs in the same
+//! positions. Lanes leaving that part are not copied: the copies of the inputs
+//! and outputs are left for the caller to link further, and other functions
+//! lose the outputs they sent elsewhere. Inputs are always copied, so variables
+//! holding data can be given as inputs to feed the copy on its own. Other
+//! variables are copied with their current values, or with
+//! [`Parameters::Share`] left in place and given lanes of their own into the
+//! copies, holding the values of the lanes they copy. The lanes of the
+//! original are left alone, so a backward pass through either graph reaches
+//! the variable; its gradient is the sum of those of its lanes. The copies are
+//! owned by a [`Subgraph`] the caller keeps alongside the original graph, and
+//! linked as they are made.
+use {
+    crate::{
+        arrow::{ArrowType, Connection, VjpType},
+        func::{Function, FunctionOn},
+        types::ContinuousDomain,
+        TFN,
+    },
+    std::{cell::OnceCell, collections::HashMap, rc::Rc},
+};
+
+pub(crate) fn key<D: ContinuousDomain>(f: &Function<'_, D>) -> usize {
+    f as *const Function<'_, D> as *const () as usize
+}
+
+/// the functions some outputs depend on, up to some inputs
+pub(crate) struct Connected<'a, D: ContinuousDomain> {
+    pub nodes: Vec<&'a Function<'a, D>>,
+    pub index: HashMap<usize, usize>,
+    /// the inputs come first among the nodes
+    inputs: usize,
+}
+
+impl<'a, D: ContinuousDomain> Connected<'a, D> {
+    /// walk upstream from `outputs`, stopping at `inputs`
+    pub fn between(inputs: &[&'a Function<'a, D>], outputs: &[&'a Function<'a, D>]) -> Self {
+        let mut connected = Connected {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            inputs: 0,
+        };
+        for f in inputs {
+            connected.add(f);
+        }
+        connected.inputs = connected.nodes.len();
+        let mut stack = outputs.to_vec();
+        while let Some(f) = stack.pop() {
+            if connected.add(f) {
+                stack.extend(f.on_f(|a| a.domain().iter().map(|c| c.source()).collect::<Vec<_>>()));
+            }
+        }
+        connected
+    }
+    /// add `f` unless it is there already
+    fn add(&mut self, f: &'a Function<'a, D>) -> bool {
+        if self.index.contains_key(&key(f)) {
+            return false;
+        }
+        self.index.insert(key(f), self.nodes.len());
+        self.nodes.push(f);
+        true
+    }
+    pub fn is_input(&self, i: usize) -> bool {
+        i < self.inputs
+    }
+    pub fn position(&self, f: &Function<'_, D>) -> Option<usize> {
+        self.index.get(&key(f)).copied()
+    }
+    /// whether `c` runs between two of the functions, into one that is not an
+    /// input
+    fn inside(&self, c: &Connection<'a, D>) -> bool {
+        self.position(c.source()).is_some()
+            && self.position(c.target()).is_some_and(|t| !self.is_input(t))
+    }
+    /// the positions of the lanes of the function at `i` that stay among the
+    /// functions, out of all its outputs
+    pub fn kept_lanes(&self, i: usize) -> Vec<usize> {
+        self.nodes[i].on_f(|a| {
+            (a.codomain().iter().enumerate())
+                .filter(|(_, c)| self.inside(c))
+                .map(|(p, _)| p)
+                .collect()
+        })
+    }
+    /// the connections among the functions as `link_to` calls, ordered so that
+    /// each comes after those before it in both the lanes of its source and of
+    /// its target
+    pub fn links(&self) -> Vec<(usize, usize)> {
+        let nodes = &self.nodes;
+        let outgoing = nodes
+            .iter()
+            .map(|f| {
+                f.on_f(|a| {
+                    (a.codomain().iter())
+                        .filter(|c| self.inside(c))
+                        .map(|c| (c.id(), self.index[&key(c.target())]))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let incoming = nodes
+            .iter()
+            .map(|f| f.on_f(|a| a.domain().iter().map(|c| c.id()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let total = outgoing.iter().map(Vec::len).sum::<usize>();
+        let (mut next_out, mut next_in) = (vec![0; nodes.len()], vec![0; nodes.len()]);
+        let mut links = Vec::new();
+        while links.len() < total {
+            let ready = (0..nodes.len()).find_map(|s| {
+                let (id, t) = *outgoing[s].get(next_out[s])?;
+                (incoming[t][next_in[t]] == id).then_some((s, t))
+            });
+            let (s, t) = ready.expect("the lanes of the subgraph are inconsistent");
+            next_out[s] += 1;
+            next_in[t] += 1;
+            links.push((s, t));
+        }
+        links
+    }
+}
+
+/// the maps of a function with `lanes` outputs reduced to those at `kept`;
+/// the gradients of the others are zeros shaped like them
+pub(crate) fn select_outputs<D: ContinuousDomain>(
+    forward: Rc<ArrowType<D>>,
+    vjp: Rc<VjpType<D>>,
+    kept: &[usize],
+    lanes: usize,
+) -> (Rc<ArrowType<D>>, Rc<VjpType<D>>) {
+    if kept.len() == lanes {
+        return (forward, vjp);
+    }
+    let (kept, kept_back) = (Rc::new(kept.to_vec()), Rc::new(kept.to_vec()));
+    let forward_back = forward.clone();
+    let forward: ArrowType<D> = Box::new(move |xs| {
+        let ys = forward(xs);
+        kept.iter().map(|k| ys[*k].clone()).collect()
+    });
+    let vjp: VjpType<D> = Box::new(move |xs, gys| {
+        let mut all = forward_back(xs)
+            .iter()
+            .map(|y| y.zeros_like())
+            .collect::<Vec<_>>();
+        for (k, gy) in kept_back.iter().zip(gys) {
+            all[*k] = gy.clone();
+        }
+        vjp(xs, &all)
+    });
+    (Rc::new(forward), Rc::new(vjp))
+}
+
+/// what a copy does with the variables of the original
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameters {
+    Copy,
+    Share,
+}
+
+enum Slot {
+    Copy(usize),
+    Shared,
+}
+
+struct Copies<'a, D: ContinuousDomain> {
+    connected: Connected<'a, D>,
+    slots: Vec<Slot>,
+    functions: Vec<Function<'a, D>>,
+}
+
+/// the copies made by [`clone_subgraph`]
+#[derive(Default)]
+pub struct Subgraph<'a, D: ContinuousDomain>(OnceCell<Copies<'a, D>>);
+
+impl<'a, D: ContinuousDomain> Subgraph<'a, D> {
+    pub fn new() -> Self {
+        Subgraph(OnceCell::new())
+    }
+    /// the copy of `original`, or `original` itself for a shared variable
+    pub fn copy_of(&self, original: &Function<'_, D>) -> &Function<'a, D> {
+        let copies = self.0.get().expect("nothing was cloned into the subgraph");
+        let i = (copies.connected)
+            .position(original)
+            .expect("the function is not part of the subgraph");
+        match copies.slots[i] {
+            Slot::Copy(k) => &copies.functions[k],
+            Slot::Shared => copies.connected.nodes[i],
+        }
+    }
+}
+
+/// copy the functions `outputs` depend on, up to `inputs`, into `into`
+pub fn clone_subgraph<'a, D: ContinuousDomain>(
+    into: &'a Subgraph<'a, D>,
+    inputs: &[&'a Function<'a, D>],
+    outputs: &[&'a Function<'a, D>],
+    parameters: Parameters,
+) {
+    let connected = Connected::between(inputs, outputs);
+    let links = connected.links();
+    let mut functions = Vec::new();
+    let slots = (0..connected.nodes.len())
+        .map(|i| match copy(&connected, i, outputs, parameters) {
+            Some(f) => {
+                functions.push(f);
+                Slot::Copy(functions.len() - 1)
+            }
+            None => Slot::Shared,
+        })
+        .collect::<Vec<_>>();
+    let copies = Copies {
+        connected,
+        slots,
+        functions,
+    };
+    assert!(
+        into.0.set(copies).is_ok(),
+        "the subgraph holds copies already"
+    );
+    let copies = into.0.get().unwrap();
+    let nodes = &copies.connected.nodes;
+    // the values a shared variable sends along its lanes into the subgraph
+    let mut shared = (0..nodes.len())
+        .map(|i| match copies.slots[i] {
+            Slot::Copy(_) => Vec::new(),
+            Slot::Shared => {
+                let values = nodes[i].on_f(|a| a.outputs());
+                let kept = copies.connected.kept_lanes(i);
+                kept.iter().rev().map(|k| values[*k].clone()).collect()
+            }
+        })
+        .collect::<Vec<_>>();
+    for (s, t) in links {
+        let Slot::Copy(target) = copies.slots[t] else {
+            unreachable!("a variable has no inputs")
+        };
+        let target = &copies.functions[target];
+        match copies.slots[s] {
+            Slot::Copy(k) => copies.functions[k].link_to(target),
+            Slot::Shared => nodes[s].link_with_value(target, shared[s].pop().unwrap()),
+        }
+    }
+}
+
+/// the copy of the function at `i` without the lanes leaving the subgraph, or
+/// `None` for a shared variable
+fn copy<'a, D: ContinuousDomain>(
+    connected: &Connected<'a, D>,
+    i: usize,
+    outputs: &[&'a Function<'a, D>],
+    parameters: Parameters,
+) -> Option<Function<'a, D>> {
+    let f = connected.nodes[i];
+    let kept = connected.kept_lanes(i);
+    assert!(
+        !connected.is_input(i) || !kept.is_empty(),
+        "the input {} does not reach the outputs",
+        f.name()
+    );
+    if f.on_f(|a| a.is_coterminal()) {
+        let copied = parameters == Parameters::Copy || connected.is_input(i);
+        return copied.then(|| f.copy_lanes(&kept));
+    }
+    let lanes = f.on_f(|a| a.codomain().len());
+    if kept.len() == lanes || outputs.iter().any(|o| key(*o) == key(f)) {
+        assert!(
+            kept.iter().enumerate().all(|(p, k)| p == *k),
+            "the lanes of {} leaving the subgraph come before some staying in it",
+            f.name()
+        );
+        return Some(f.clone());
+    }
+    let (forward, vjp) = select_outputs(f.forward_arrow(), f.backward_vjp(), &kept, lanes);
+    let copy = Function::with_vjp(
+        TFN!(move |xs: &[D]| forward(xs)),
+        TFN!(move |xs: &[D], gys: &[D]| vjp(xs, gys)),
+    )
+    .named(&f.name());
+    Some(match f.shape_rule() {
+        Some(rule) => copy.with_shape_rule(Box::new(move |shapes| {
+            rule(shapes).map(|shapes| kept.iter().map(|k| shapes[*k].clone()).collect())
+        })),
+        None => copy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DFN, TERMINAL, TFN, VARIABLE};
+    fn mul<'a>() -> Function<'a, f64> {
+        Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[1]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![gs[0] * xs[1], gs[0] * xs[0]]),
+        )
+        .named("mul")
+    }
+    #[test]
+    fn test_clone_subgraph_copy() {
+        // y = sin(x w), copied as a target network
+        let x: Function<f64> = VARIABLE!(0.5, 0.5);
+        let w: Function<f64> = VARIABLE!(2.0);
+        let m = mul();
+        let s: Function<f64> =
+            Function::<f64>::new(DFN!(|x: f64| x.sin()), DFN!(|x: f64| x.cos())).named("sin");
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        w.link_to(&m);
+        m.followed_by(&s).followed_by(&y);
+        // a branch the copy leaves out
+        let other: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&other);
+        let copy = Subgraph::new();
+        clone_subgraph(&copy, &[&x], &[&y], Parameters::Copy);
+        let (x2, w2, y2) = (copy.copy_of(&x), copy.copy_of(&w), copy.copy_of(&y));
+        assert_eq!(copy.copy_of(&s).name(), "sin");
+        assert_eq!(x2.on_f(|a| a.outputs()), vec![0.5]);
+        // the copy moves on its own
+        w2.assign(vec![3.0]);
+        for v in [&x, &w, x2, w2] {
+            v.propagate_forward();
+        }
+        Function::backward_from(&[(&y, vec![1.0]), (&other, vec![0.0])]);
+        y2.propagate_backward();
+        assert_eq!(y.on_f(|a| a.outputs()), vec![1.0f64.sin()]);
+        assert_eq!(y2.on_f(|a| a.outputs()), vec![1.5f64.sin()]);
+        assert_eq!(w.on_b(|a| a.outputs()), vec![0.5 * 1.0f64.cos()]);
+        assert_eq!(w2.on_b(|a| a.outputs()), vec![0.5 * 1.5f64.cos()]);
+        // the lanes keep their order: x stays the first input of the copied mul
+        assert_eq!(x2.on_b(|a| a.outputs()), vec![3.0 * 1.5f64.cos()]);
+        assert_eq!(m.on_f(|a| a.codomain().len()), 1);
+    }
+    #[test]
+    fn test_clone_subgraph_share() {
+        // two copies of w x reading the same w
+        let x: Function<f64> = VARIABLE!(3.0);
+        let w: Function<f64> = VARIABLE!(2.0);
+        let m = mul();
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        w.link_to(&m);
+        m.link_to(&y);
+        let copy = Subgraph::new();
+        clone_subgraph(&copy, &[&x], &[&y], Parameters::Share);
+        assert!(std::ptr::eq(copy.copy_of(&w), &w));
+        let (x2, y2) = (copy.copy_of(&x), copy.copy_of(&y));
+        assert!(!std::ptr::eq(x2, &x));
+        // w keeps its lane into the original and gets another into the copy
+        assert_eq!(w.on_f(|a| a.outputs()), vec![2.0, 2.0]);
+        assert!(std::ptr::eq(w.on_f(|a| a.codomain()[0].target()), &m));
+        x2.assign(vec![5.0]);
+        for v in [&x, x2, &w] {
+            v.propagate_forward();
+        }
+        assert_eq!(y.on_f(|a| a.outputs()), vec![6.0]);
+        assert_eq!(y2.on_f(|a| a.outputs()), vec![10.0]);
+        Function::backward_from(&[(&y, vec![1.0]), (y2, vec![1.0])]);
+        assert_eq!(w.on_b(|a| a.outputs()), vec![3.0, 5.0]);
+        w.assign(vec![4.0, 4.0]);
+        for v in [&x, x2, &w] {
+            v.propagate_forward();
+        }
+        assert_eq!(y.on_f(|a| a.outputs()), vec![12.0]);
+        assert_eq!(y2.on_f(|a| a.outputs()), vec![20.0]);
+    }
+    #[test]
+    fn test_clone_subgraph_share_original_alone() {
+        // the original still reaches w after a copy shares it
+        let x: Function<f64> = VARIABLE!(3.0);
+        let w: Function<f64> = VARIABLE!(2.0);
+        let m = mul();
+        let y: Function<f64> = TERMINAL!(1.0);
+        x.link_to(&m);
+        w.link_to(&m);
+        m.link_to(&y);
+        let copy = Subgraph::new();
+        clone_subgraph(&copy, &[&x], &[&y], Parameters::Share);
+        copy.copy_of(&x).assign(vec![5.0]);
+        for v in [&x, copy.copy_of(&x), &w] {
+            v.propagate_forward();
+        }
+        y.propagate_backward();
+        assert_eq!(w.on_b(|a| a.outputs()), vec![3.0, 0.0]);
+        assert_eq!(x.on_b(|a| a.outputs()), vec![2.0]);
+    }
+    #[test]
+    fn test_clone_subgraph_block() {
+        // the block x -> exp(x^2) up to the exp, whose square also feeds a
+        // terminal outside the block
+        let x: Function<f64> = VARIABLE!(0.5);
+        let split = Function::with_vjp(
+            TFN!(|xs: &[f64]| vec![xs[0] * xs[0], 3.0 * xs[0]]),
+            TFN!(|xs: &[f64], gs: &[f64]| vec![2.0 * xs[0] * gs[0] + 3.0 * gs[1]]),
+        );
+        let e = Function::<f64>::new(DFN!(|x: f64| x.exp()), DFN!(|x: f64| x.exp())).named("exp");
+        let (y, thrice): (Function<f64>, Function<f64>) = (TERMINAL!(1.0), TERMINAL!(1.0));
+        x.followed_by(&split).followed_by(&e).followed_by(&y);
+        split.link_to(&thrice);
+        let block = Subgraph::new();
+        clone_subgraph(&block, &[&x], &[&e], Parameters::Copy);
+        let (x2, e2) = (block.copy_of(&x), block.copy_of(&e));
+        // the copy of the output is left for the caller to link
+        assert_eq!(e2.on_f(|a| a.codomain().len()), 0);
+        let y2: Function<f64> = TERMINAL!(1.0);
+        e2.link_to(&y2);
+        x2.propagate_forward();
+        y2.propagate_backward();
+        assert_eq!(y2.on_f(|a| a.outputs()), vec![0.25f64.exp()]);
+        assert_eq!(x2.on_b(|a| a.outputs()), vec![0.25f64.exp()]);
+    }
+}
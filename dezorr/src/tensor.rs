@@ -0,0 +1,810 @@
+use {
+    crate::types::{ContinuousDomain, OnesLike},
+    std::{fmt, rc::Rc},
+};
+
+/// An n-dimensional array as a strided view over reference-counted storage.
+///
+/// Cloning, reshaping a contiguous tensor, transposing, slicing and
+/// broadcasting share the storage instead of copying it, so values passed
+/// along graph connections are cheap to clone. Operations that need the
+/// elements in row-major order call [`Tensor::contiguous`].
+#[derive(Clone)]
+pub struct Tensor<T: ContinuousDomain> {
+    storage: Rc<Vec<T>>,
+    offset: usize,
+    shape: Vec<usize>,
+    strides: Vec<isize>,
+}
+
+/// An index on a single axis, used by [`Tensor::get_item`].
+///
+/// Each index selects along its own axis independently, so two `Take`s on
+/// different axes pick an outer product of positions like `np.ix_`. To pick one
+/// position per row, as in `x[i, labels[i]]`, use [`Tensor::take_along_axis`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Index {
+    /// a single position; the axis is dropped. Negative values count from the end.
+    At(isize),
+    /// positions from `start` to `stop` by `step`, following Python's slice rules
+    Slice {
+        start: Option<isize>,
+        stop: Option<isize>,
+        step: isize,
+    },
+    /// the listed positions in order; repeated positions are allowed
+    Take(Vec<isize>),
+    /// the positions where the mask is `true`
+    Mask(Vec<bool>),
+}
+
+impl Index {
+    pub fn full() -> Self {
+        Index::Slice {
+            start: None,
+            stop: None,
+            step: 1,
+        }
+    }
+    pub fn range(start: isize, stop: isize) -> Self {
+        Index::Slice {
+            start: Some(start),
+            stop: Some(stop),
+            step: 1,
+        }
+    }
+    /// the length of the axis this index leaves from one of length `len`, or
+    /// `None` when the axis is dropped; the same checks as selecting, without data
+    pub fn selected_len(&self, len: usize) -> Result<Option<usize>, String> {
+        let n = len as isize;
+        let check = |i: &isize| {
+            (-n <= *i && *i < n)
+                .then_some(())
+                .ok_or_else(|| format!("index {i} is out of bounds for length {len}"))
+        };
+        match self {
+            Index::At(i) => check(i)?,
+            Index::Slice { step: 0, .. } => return Err("slice step cannot be zero".to_string()),
+            Index::Take(is) => is.iter().try_for_each(check)?,
+            Index::Mask(mask) if mask.len() != len => {
+                return Err("mask length does not match the axis".to_string())
+            }
+            _ => {}
+        }
+        let (positions, keep) = self.positions(len);
+        Ok(keep.then_some(positions.len()))
+    }
+    /// return the selected positions and whether the axis remains
+    fn positions(&self, len: usize) -> (Vec<usize>, bool) {
+        let n = len as isize;
+        let at = |i: isize| -> usize {
+            let j = if i < 0 { i + n } else { i };
+            assert!(
+                0 <= j && j < n,
+                "index {i} is out of bounds for length {len}"
+            );
+            j as usize
+        };
+        match self {
+            Index::At(i) => (vec![at(*i)], false),
+            Index::Slice { start, stop, step } => {
+                let step = *step;
+                assert!(step != 0, "slice step cannot be zero");
+                let clamp = |i: isize, lo: isize, hi: isize| -> isize {
+                    (if i < 0 { i + n } else { i }).clamp(lo, hi)
+                };
+                let (start, stop) = if 0 < step {
+                    (
+                        start.map_or(0, |i| clamp(i, 0, n)),
+                        stop.map_or(n, |i| clamp(i, 0, n)),
+                    )
+                } else {
+                    (
+                        start.map_or(n - 1, |i| clamp(i, -1, n - 1)),
+                        stop.map_or(-1, |i| clamp(i, -1, n - 1)),
+                    )
+                };
+                let mut result = Vec::new();
+                let mut i = start;
+                while (0 < step && i < stop) || (step < 0 && stop < i) {
+                    result.push(i as usize);
+                    i += step;
+                }
+                (result, true)
+            }
+            Index::Take(is) => (is.iter().map(|i| at(*i)).collect(), true),
+            Index::Mask(mask) => {
+                assert_eq!(mask.len(), len, "mask length does not match the axis");
+                (
+                    mask.iter()
+                        .enumerate()
+                        .filter(|(_, b)| **b)
+                        .map(|(i, _)| i)
+                        .collect(),
+                    true,
+                )
+            }
+        }
+    }
+}
+
+impl<T: ContinuousDomain> Default for Tensor<T> {
+    fn default() -> Self {
+        Tensor::scalar(T::default())
+    }
+}
+
+impl<T: ContinuousDomain> PartialEq for Tensor<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.shape == other.shape && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: ContinuousDomain> fmt::Debug for Tensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tensor")
+            .field("shape", &self.shape)
+            .field("data", &self.to_vec())
+            .finish()
+    }
+}
+
+impl<T: ContinuousDomain> Tensor<T> {
+    pub fn new(shape: Vec<usize>, data: Vec<T>) -> Self {
+        assert_eq!(
+            shape.iter().product::<usize>(),
+            data.len(),
+            "shape {shape:?} does not match {} elements",
+            data.len()
+        );
+        Tensor {
+            storage: Rc::new(data),
+            offset: 0,
+            strides: strides_of(&shape),
+            shape,
+        }
+    }
+    pub fn scalar(value: T) -> Self {
+        Tensor::new(Vec::new(), vec![value])
+    }
+    pub fn vector(data: Vec<T>) -> Self {
+        Tensor::new(vec![data.len()], data)
+    }
+    pub fn filled(shape: &[usize], value: T) -> Self {
+        Tensor::new(shape.to_vec(), vec![value; shape.iter().product()])
+    }
+    pub fn zeros(shape: &[usize]) -> Self {
+        Tensor::filled(shape, T::default())
+    }
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+    pub fn strides(&self) -> &[isize] {
+        &self.strides
+    }
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// whether both tensors are views of the same storage
+    pub fn shares_storage(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.storage, &other.storage)
+    }
+    /// whether the elements are laid out in row-major order without gaps
+    pub fn is_contiguous(&self) -> bool {
+        let mut expected = 1;
+        for (n, s) in self.shape.iter().zip(self.strides.iter()).rev() {
+            if *n != 1 && *s != expected {
+                return false;
+            }
+            expected *= *n as isize;
+        }
+        true
+    }
+    /// the elements in row-major order, if they are contiguous in storage
+    pub fn as_slice(&self) -> Option<&[T]> {
+        self.is_contiguous()
+            .then(|| &self.storage[self.offset..self.offset + self.len()])
+    }
+    /// the storage positions of the elements in row-major order
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut index = vec![0; self.ndim()];
+        let mut offset = self.offset as isize;
+        (0..self.len()).map(move |k| {
+            if 0 < k {
+                for axis in (0..index.len()).rev() {
+                    index[axis] += 1;
+                    offset += self.strides[axis];
+                    if index[axis] < self.shape[axis] {
+                        break;
+                    }
+                    offset -= self.strides[axis] * self.shape[axis] as isize;
+                    index[axis] = 0;
+                }
+            }
+            offset as usize
+        })
+    }
+    /// the elements in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.offsets().map(|o| &self.storage[o])
+    }
+    pub fn to_vec(&self) -> Vec<T> {
+        match self.as_slice() {
+            Some(slice) => slice.to_vec(),
+            None => self.iter().cloned().collect(),
+        }
+    }
+    /// the same elements in fresh row-major storage, or `self` if it is already contiguous
+    pub fn contiguous(&self) -> Self {
+        if self.is_contiguous() {
+            self.clone()
+        } else {
+            Tensor::new(self.shape.clone(), self.to_vec())
+        }
+    }
+    pub fn get(&self, index: &[usize]) -> &T {
+        assert_eq!(index.len(), self.ndim());
+        let offset = index
+            .iter()
+            .zip(self.strides.iter())
+            .zip(self.shape.iter())
+            .map(|((i, s), n)| {
+                assert!(
+                    i < n,
+                    "index {index:?} is out of bounds for {:?}",
+                    self.shape
+                );
+                *i as isize * s
+            })
+            .sum::<isize>();
+        &self.storage[(self.offset as isize + offset) as usize]
+    }
+    pub fn map(&self, f: impl Fn(&T) -> T) -> Self {
+        Tensor::new(self.shape.clone(), self.iter().map(f).collect())
+    }
+    /// a view with a new shape; a tensor that is not contiguous is copied first
+    pub fn reshape(&self, shape: &[usize]) -> Self {
+        assert_eq!(
+            shape.iter().product::<usize>(),
+            self.len(),
+            "cannot reshape {:?} into {shape:?}",
+            self.shape
+        );
+        if !self.is_contiguous() {
+            return self.contiguous().reshape(shape);
+        }
+        Tensor {
+            storage: self.storage.clone(),
+            offset: self.offset,
+            shape: shape.to_vec(),
+            strides: strides_of(shape),
+        }
+    }
+    /// a view with the axes reordered so that axis `i` of the result is axis `axes[i]`
+    pub fn permute(&self, axes: &[usize]) -> Self {
+        let mut seen = vec![false; self.ndim()];
+        for a in axes.iter() {
+            assert!(
+                *a < self.ndim() && !seen[*a],
+                "{axes:?} is not a permutation of the axes of {:?}",
+                self.shape
+            );
+            seen[*a] = true;
+        }
+        assert_eq!(axes.len(), self.ndim());
+        Tensor {
+            storage: self.storage.clone(),
+            offset: self.offset,
+            shape: axes.iter().map(|a| self.shape[*a]).collect(),
+            strides: axes.iter().map(|a| self.strides[*a]).collect(),
+        }
+    }
+    /// a view with the axes in reverse order
+    pub fn transpose(&self) -> Self {
+        self.permute(&(0..self.ndim()).rev().collect::<Vec<_>>())
+    }
+    /// a view repeating the elements along broadcast axes, without copying
+    pub fn broadcast_to(&self, shape: &[usize]) -> Self {
+        assert!(
+            broadcast_shapes(&self.shape, shape).as_deref() == Some(shape),
+            "{:?} cannot be broadcast to {shape:?}",
+            self.shape
+        );
+        let pad = shape.len() - self.ndim();
+        Tensor {
+            storage: self.storage.clone(),
+            offset: self.offset,
+            shape: shape.to_vec(),
+            strides: (0..shape.len())
+                .map(|i| {
+                    if i < pad || self.shape[i - pad] != shape[i] {
+                        0
+                    } else {
+                        self.strides[i - pad]
+                    }
+                })
+                .collect(),
+        }
+    }
+    /// sum over the axes along which `shape` was broadcast to the shape of `self`
+    pub fn sum_to(&self, shape: &[usize]) -> Self {
+        let mut result: Self = Tensor::zeros(shape);
+        let targets = result
+            .broadcast_to(&self.shape)
+            .offsets()
+            .collect::<Vec<_>>();
+        let data = Rc::make_mut(&mut result.storage);
+        for (o, x) in targets.into_iter().zip(self.iter()) {
+            data[o] = data[o].clone() + x.clone();
+        }
+        result
+    }
+    /// apply `f` elementwise after broadcasting both operands to a common shape
+    pub fn zip_with(&self, other: &Self, f: impl Fn(&T, &T) -> T) -> Self {
+        if self.shape == other.shape {
+            if let (Some(a), Some(b)) = (self.as_slice(), other.as_slice()) {
+                return Tensor::new(
+                    self.shape.clone(),
+                    a.iter().zip(b.iter()).map(|(a, b)| f(a, b)).collect(),
+                );
+            }
+        }
+        let shape = broadcast_shapes(&self.shape, &other.shape).unwrap_or_else(|| {
+            panic!(
+                "shapes {:?} and {:?} cannot be broadcast",
+                self.shape, other.shape
+            )
+        });
+        let (a, b) = (self.broadcast_to(&shape), other.broadcast_to(&shape));
+        let data = a.iter().zip(b.iter()).map(|(a, b)| f(a, b)).collect();
+        Tensor::new(shape, data)
+    }
+    /// the matrix product of two 2-D tensors
+    pub fn matmul(&self, other: &Self) -> Self {
+        assert!(
+            self.ndim() == 2 && other.ndim() == 2 && self.shape[1] == other.shape[0],
+            "shapes {:?} and {:?} cannot be multiplied",
+            self.shape,
+            other.shape
+        );
+        let (n, k, m) = (self.shape[0], self.shape[1], other.shape[1]);
+        let (a, b) = (self.contiguous(), other.contiguous());
+        let (a, b) = (a.as_slice().unwrap(), b.as_slice().unwrap());
+        let mut out = vec![T::default(); n * m];
+        for i in 0..n {
+            for p in 0..k {
+                for j in 0..m {
+                    out[i * m + j] =
+                        out[i * m + j].clone() + a[i * k + p].clone() * b[p * m + j].clone();
+                }
+            }
+        }
+        Tensor::new(vec![n, m], out)
+    }
+    /// select a sub-tensor; axes without an index are taken whole
+    ///
+    /// Positions and slices give a view; `Take` and `Mask` gather a copy.
+    pub fn get_item(&self, index: &[Index]) -> Self {
+        if index
+            .iter()
+            .any(|i| matches!(i, Index::Take(_) | Index::Mask(_)))
+        {
+            let (shape, offsets) = self.item_offsets(index);
+            return Tensor::new(
+                shape,
+                offsets.iter().map(|o| self.storage[*o].clone()).collect(),
+            );
+        }
+        assert!(
+            index.len() <= self.ndim(),
+            "too many indices for a tensor of shape {:?}",
+            self.shape
+        );
+        let mut view = Tensor {
+            storage: self.storage.clone(),
+            offset: self.offset,
+            shape: Vec::new(),
+            strides: Vec::new(),
+        };
+        for (axis, (n, stride)) in self.shape.iter().zip(self.strides.iter()).enumerate() {
+            let Some(i) = index.get(axis) else {
+                view.shape.push(*n);
+                view.strides.push(*stride);
+                continue;
+            };
+            let (positions, keep) = i.positions(*n);
+            if let Some(first) = positions.first() {
+                view.offset = (view.offset as isize + *first as isize * stride) as usize;
+            }
+            if keep {
+                let step = match i {
+                    Index::Slice { step, .. } => *step,
+                    _ => 1,
+                };
+                view.shape.push(positions.len());
+                view.strides.push(stride * step);
+            }
+        }
+        view
+    }
+    /// add `values` into the positions selected by `index`, accumulating on repeats
+    pub fn index_add(self, index: &[Index], values: &Self) -> Self {
+        let mut result = self.contiguous();
+        let (shape, offsets) = result.item_offsets(index);
+        assert_eq!(shape, values.shape, "values do not match the selection");
+        let data = Rc::make_mut(&mut result.storage);
+        for (o, v) in offsets.iter().zip(values.iter()) {
+            data[*o] = data[*o].clone() + v.clone();
+        }
+        result
+    }
+    /// the elements at `indices` along `axis`, like NumPy's `take_along_axis`:
+    /// `indices` has the shape of `self` except on `axis`, and so has the result
+    pub fn take_along_axis(&self, indices: &Tensor<usize>, axis: usize) -> Self {
+        let offsets = self.along_axis_offsets(indices, axis);
+        Tensor::new(
+            indices.shape.clone(),
+            offsets.iter().map(|o| self.storage[*o].clone()).collect(),
+        )
+    }
+    /// add `values` into the positions picked by `indices` along `axis`,
+    /// accumulating on repeats; the inverse of [`Tensor::take_along_axis`]
+    pub fn add_along_axis(self, indices: &Tensor<usize>, axis: usize, values: &Self) -> Self {
+        let mut result = self.contiguous();
+        assert_eq!(
+            indices.shape, values.shape,
+            "values do not match the indices"
+        );
+        let offsets = result.along_axis_offsets(indices, axis);
+        let data = Rc::make_mut(&mut result.storage);
+        for (o, v) in offsets.iter().zip(values.iter()) {
+            data[*o] = data[*o].clone() + v.clone();
+        }
+        result
+    }
+    /// join tensors along an existing axis
+    pub fn concat(tensors: &[Self], axis: usize) -> Self {
+        assert!(!tensors.is_empty(), "nothing to concatenate");
+        let first = &tensors[0].shape;
+        assert!(
+            axis < first.len(),
+            "axis {axis} is out of bounds for {first:?}"
+        );
+        for t in tensors.iter() {
+            assert!(
+                t.ndim() == first.len()
+                    && (0..first.len()).all(|i| i == axis || t.shape[i] == first[i]),
+                "shapes {first:?} and {:?} cannot be concatenated on axis {axis}",
+                t.shape
+            );
+        }
+        let parts = tensors.iter().map(|t| t.contiguous()).collect::<Vec<_>>();
+        let outer = first[..axis].iter().product::<usize>();
+        let inner = first[axis + 1..].iter().product::<usize>();
+        let mut shape = first.clone();
+        shape[axis] = tensors.iter().map(|t| t.shape[axis]).sum();
+        let mut data = Vec::with_capacity(shape.iter().product());
+        for o in 0..outer {
+            for t in parts.iter() {
+                let slab = t.shape[axis] * inner;
+                data.extend_from_slice(&t.as_slice().unwrap()[o * slab..(o + 1) * slab]);
+            }
+        }
+        Tensor::new(shape, data)
+    }
+    /// join tensors of the same shape along a new axis
+    pub fn stack(tensors: &[Self], axis: usize) -> Self {
+        let parts = tensors
+            .iter()
+            .map(|t| {
+                assert!(
+                    axis <= t.ndim(),
+                    "axis {axis} is out of bounds for {:?}",
+                    t.shape
+                );
+                let mut shape = t.shape.clone();
+                shape.insert(axis, 1);
+                t.reshape(&shape)
+            })
+            .collect::<Vec<_>>();
+        Tensor::concat(&parts, axis)
+    }
+    /// cut into pieces of the given sizes along `axis`
+    pub fn split(&self, sizes: &[usize], axis: usize) -> Vec<Self> {
+        assert!(
+            axis < self.ndim(),
+            "axis {axis} is out of bounds for {:?}",
+            self.shape
+        );
+        assert_eq!(
+            sizes.iter().sum::<usize>(),
+            self.shape[axis],
+            "sizes {sizes:?} do not cover axis {axis} of {:?}",
+            self.shape
+        );
+        let mut index = vec![Index::full(); axis + 1];
+        let mut start = 0;
+        sizes
+            .iter()
+            .map(|n| {
+                index[axis] = Index::range(start as isize, (start + n) as isize);
+                start += n;
+                self.get_item(&index)
+            })
+            .collect()
+    }
+    /// cut into `chunks` pieces of nearly equal size along `axis`, like
+    /// `np.array_split`; the first ones take the remainder
+    pub fn chunk(&self, chunks: usize, axis: usize) -> Vec<Self> {
+        self.split(&chunk_sizes(self.shape[axis], chunks), axis)
+    }
+    /// the slices along a removed `axis`; the inverse of [`Tensor::stack`]
+    pub fn unstack(&self, axis: usize) -> Vec<Self> {
+        let mut index = vec![Index::full(); axis + 1];
+        (0..self.shape[axis])
+            .map(|i| {
+                index[axis] = Index::At(i as isize);
+                self.get_item(&index)
+            })
+            .collect()
+    }
+    /// the storage positions of the elements picked by `indices` along `axis`
+    fn along_axis_offsets(&self, indices: &Tensor<usize>, axis: usize) -> Vec<usize> {
+        assert!(
+            axis < self.ndim(),
+            "axis {axis} is out of bounds for {:?}",
+            self.shape
+        );
+        assert!(
+            indices.ndim() == self.ndim()
+                && (0..self.ndim()).all(|a| a == axis || indices.shape[a] == self.shape[a]),
+            "indices of shape {:?} do not match {:?} off axis {axis}",
+            indices.shape,
+            self.shape
+        );
+        let len = self.shape[axis];
+        let steps = strides_of(&indices.shape);
+        indices
+            .iter()
+            .enumerate()
+            .map(|(k, i)| {
+                assert!(*i < len, "index {i} is out of bounds for length {len}");
+                let offset = (0..self.ndim())
+                    .map(|a| {
+                        let p = if a == axis {
+                            *i
+                        } else {
+                            k / steps[a] as usize % indices.shape[a]
+                        };
+                        p as isize * self.strides[a]
+                    })
+                    .sum::<isize>();
+                (self.offset as isize + offset) as usize
+            })
+            .collect()
+    }
+    /// the storage positions selected by `index`, and the shape they form
+    fn item_offsets(&self, index: &[Index]) -> (Vec<usize>, Vec<usize>) {
+        assert!(
+            index.len() <= self.ndim(),
+            "too many indices for a tensor of shape {:?}",
+            self.shape
+        );
+        let mut shape = Vec::new();
+        let mut offsets = vec![self.offset as isize];
+        for (axis, (n, stride)) in self.shape.iter().zip(self.strides.iter()).enumerate() {
+            let (positions, keep) = index
+                .get(axis)
+                .map_or_else(|| ((0..*n).collect(), true), |i| i.positions(*n));
+            if keep {
+                shape.push(positions.len());
+            }
+            offsets = offsets
+                .iter()
+                .flat_map(|o| positions.iter().map(move |p| o + *p as isize * stride))
+                .collect();
+        }
+        (shape, offsets.into_iter().map(|o| o as usize).collect())
+    }
+}
+
+/// the sizes [`Tensor::chunk`] cuts an axis of length `len` into
+pub fn chunk_sizes(len: usize, chunks: usize) -> Vec<usize> {
+    assert!(0 < chunks, "the number of chunks must be positive");
+    let (size, extra) = (len / chunks, len % chunks);
+    (0..chunks).map(|i| size + usize::from(i < extra)).collect()
+}
+
+fn strides_of(shape: &[usize]) -> Vec<isize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1] as isize;
+    }
+    strides
+}
+
+/// the shape two operands broadcast to under NumPy's rules
+pub fn broadcast_shapes(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let n = a.len().max(b.len());
+    let dim = |s: &[usize], i: usize| (i + s.len() >= n).then(|| s[i + s.len() - n]);
+    (0..n)
+        .map(|i| match (dim(a, i).unwrap_or(1), dim(b, i).unwrap_or(1)) {
+            (x, y) if x == y => Some(x),
+            (1, y) => Some(y),
+            (x, 1) => Some(x),
+            _ => None,
+        })
+        .collect()
+}
+
+macro_rules! impl_binary_op {
+    ($trait: ident, $method: ident) => {
+        impl<T: ContinuousDomain> std::ops::$trait for Tensor<T> {
+            type Output = Tensor<T>;
+            fn $method(self, other: Self) -> Self::Output {
+                self.zip_with(&other, |a, b| {
+                    std::ops::$trait::$method(a.clone(), b.clone())
+                })
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add);
+impl_binary_op!(Sub, sub);
+impl_binary_op!(Mul, mul);
+impl_binary_op!(Div, div);
+
+impl<T: ContinuousDomain> ContinuousDomain for Tensor<T> {
+    fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+    fn zeros_like(&self) -> Self {
+        Tensor::zeros(&self.shape)
+    }
+}
+
+impl<T: OnesLike> OnesLike for Tensor<T> {
+    fn ones_like(&self) -> Self {
+        self.map(|x| x.ones_like())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn arange(shape: &[usize]) -> Tensor<f64> {
+        Tensor::new(
+            shape.to_vec(),
+            (0..shape.iter().product::<usize>())
+                .map(|i| i as f64)
+                .collect(),
+        )
+    }
+    #[test]
+    fn test_tensor_broadcast() {
+        let a = arange(&[2, 3]);
+        let b = Tensor::vector(vec![10.0, 20.0, 30.0]);
+        let c = a + b;
+        assert_eq!(c.shape(), &[2, 3]);
+        assert_eq!(c.to_vec(), vec![10.0, 21.0, 32.0, 13.0, 24.0, 35.0]);
+        let d = arange(&[2, 1]) * Tensor::scalar(2.0);
+        assert_eq!(d.to_vec(), vec![0.0, 2.0]);
+        assert_eq!(broadcast_shapes(&[2, 3], &[4, 3]), None);
+    }
+    #[test]
+    fn test_tensor_get_item() {
+        let x = arange(&[3, 4]);
+        let row = x.get_item(&[Index::At(-1)]);
+        assert_eq!(row.shape(), &[4]);
+        assert_eq!(row.to_vec(), vec![8.0, 9.0, 10.0, 11.0]);
+        let cols = x.get_item(&[
+            Index::full(),
+            Index::Slice {
+                start: None,
+                stop: None,
+                step: -2,
+            },
+        ]);
+        assert_eq!(cols.shape(), &[3, 2]);
+        assert_eq!(cols.to_vec(), vec![3.0, 1.0, 7.0, 5.0, 11.0, 9.0]);
+        let picked = x.get_item(&[Index::Take(vec![2, 0, 2]), Index::At(1)]);
+        assert_eq!(picked.to_vec(), vec![9.0, 1.0, 9.0]);
+        let masked = x.get_item(&[Index::Mask(vec![true, false, true]), Index::range(1, 3)]);
+        assert_eq!(masked.shape(), &[2, 2]);
+        assert_eq!(masked.to_vec(), vec![1.0, 2.0, 9.0, 10.0]);
+    }
+    #[test]
+    fn test_tensor_concat_split() {
+        let a = arange(&[2, 2]);
+        let b = arange(&[2, 1]);
+        let c = Tensor::concat(&[a.clone(), b.clone()], 1);
+        assert_eq!(c.shape(), &[2, 3]);
+        assert_eq!(c.to_vec(), vec![0.0, 1.0, 0.0, 2.0, 3.0, 1.0]);
+        assert_eq!(c.split(&[2, 1], 1), vec![a.clone(), b]);
+        let s = Tensor::stack(&[a.clone(), a.clone()], 0);
+        assert_eq!(s.shape(), &[2, 2, 2]);
+        assert_eq!(s.unstack(0), vec![a.clone(), a]);
+        assert_eq!(chunk_sizes(5, 2), vec![3, 2]);
+        assert_eq!(chunk_sizes(5, 4), vec![2, 1, 1, 1]);
+        assert_eq!(chunk_sizes(7, 3), vec![3, 2, 2]);
+        assert_eq!(chunk_sizes(2, 3), vec![1, 1, 0]);
+        let pieces = arange(&[5]).chunk(4, 0);
+        assert_eq!(pieces.len(), 4);
+        assert!(pieces.iter().all(|p| !p.is_empty()));
+        assert_eq!(pieces[1].to_vec(), vec![2.0]);
+        assert!(arange(&[2]).chunk(3, 0)[2].is_empty());
+        assert_eq!(arange(&[5]).chunk(2, 0)[1].to_vec(), vec![3.0, 4.0]);
+    }
+    #[test]
+    fn test_tensor_index_add() {
+        let index = [Index::Take(vec![1, 1, 0])];
+        let g = Tensor::zeros(&[3]).index_add(&index, &Tensor::vector(vec![1.0, 2.0, 4.0]));
+        assert_eq!(g.to_vec(), vec![4.0, 3.0, 0.0]);
+    }
+    #[test]
+    fn test_tensor_take_along_axis() {
+        let x = arange(&[3, 4]);
+        let labels = Tensor::new(vec![3, 1], vec![2, 0, 3]);
+        let picked = x.take_along_axis(&labels, 1);
+        assert_eq!(picked.shape(), &[3, 1]);
+        assert_eq!(picked.to_vec(), vec![2.0, 4.0, 11.0]);
+        // the same on a transposed view, along the other axis
+        let rows = x
+            .transpose()
+            .take_along_axis(&Tensor::new(vec![1, 3], vec![2, 0, 3]), 0);
+        assert_eq!(rows.to_vec(), vec![2.0, 4.0, 11.0]);
+        let twice = Tensor::new(vec![1, 2], vec![1, 1]);
+        let g = Tensor::zeros(&[1, 3]).add_along_axis(
+            &twice,
+            1,
+            &Tensor::new(vec![1, 2], vec![1.0, 2.0]),
+        );
+        assert_eq!(g.to_vec(), vec![0.0, 3.0, 0.0]);
+    }
+    #[test]
+    fn test_tensor_matmul() {
+        let a = arange(&[2, 3]);
+        let b = arange(&[3, 2]);
+        assert_eq!(a.matmul(&b).to_vec(), vec![10.0, 13.0, 28.0, 40.0]);
+        assert_eq!(
+            b.transpose().matmul(&a.transpose()),
+            a.matmul(&b).transpose()
+        );
+    }
+    #[test]
+    fn test_tensor_views() {
+        let x = arange(&[3, 4]);
+        let t = x.transpose();
+        assert!(t.shares_storage(&x) && !t.is_contiguous());
+        assert_eq!(t.shape(), &[4, 3]);
+        assert_eq!(*t.get(&[1, 2]), 9.0);
+        let flat = t.reshape(&[12]);
+        assert!(!flat.shares_storage(&x));
+        assert_eq!(flat.to_vec()[..4], [0.0, 4.0, 8.0, 1.0]);
+        let row = x.get_item(&[Index::At(1)]);
+        assert!(row.shares_storage(&x) && row.is_contiguous());
+        assert_eq!(row.as_slice(), Some(&[4.0, 5.0, 6.0, 7.0][..]));
+        assert!(row.reshape(&[2, 2]).shares_storage(&x));
+        let b = Tensor::vector(vec![1.0, 2.0]).broadcast_to(&[3, 2]);
+        assert_eq!(b.strides(), &[0, 1]);
+        assert_eq!(b.to_vec(), vec![1.0, 2.0, 1.0, 2.0, 1.0, 2.0]);
+        assert_eq!(b.contiguous().strides(), &[2, 1]);
+        assert_eq!(b.sum_to(&[1, 2]).to_vec(), vec![3.0, 6.0]);
+        let parts = x.split(&[1, 3], 1);
+        assert!(parts.iter().all(|p| p.shares_storage(&x)));
+        assert_eq!(
+            parts[1].transpose().get_item(&[Index::At(-1)]).to_vec(),
+            vec![3.0, 7.0, 11.0]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Gradients of plain closures, without building a graph by hand.
+//!
+//! The closures given to [`grad`], [`value_and_grad`] and [`vmap`] compute
+//! with [`Traced`] values, which only record the operations applied to them.
+//! Each call of the returned closure lays the record out as a graph of fresh
+//! variables, functions and a terminal, propagates it and drops it again. A
+//! value used more than once feeds a fan-out function whose backward sums the
+//! gradients of its uses.
+use {
+    crate::{
+        func::{Function, FunctionOn},
+        types::Float,
+        TFN,
+    },
+    std::{collections::HashMap, rc::Rc},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+    Sqrt,
+}
+
+fn unary<'a, T: Float>(f: fn(T) -> T, df: fn(T) -> T) -> Function<'a, T> {
+    Function::new(
+        TFN!(move |xs: &[T]| xs.iter().map(|x| f(*x)).collect()),
+        TFN!(move |xs: &[T]| xs.iter().map(|x| df(*x)).collect()),
+    )
+}
+
+fn binary<'a, T: Float>(f: fn(T, T) -> T, vjp: fn(T, T, T) -> (T, T)) -> Function<'a, T> {
+    Function::with_vjp(
+        TFN!(move |xs: &[T]| vec![f(xs[0], xs[1])]),
+        TFN!(move |xs: &[T], gs: &[T]| {
+            let (ga, gb) = vjp(xs[0], xs[1], gs[0]);
+            vec![ga, gb]
+        }),
+    )
+}
+
+/// one input passed on to `k` outputs
+fn fan_out<'a, T: Float>(k: usize) -> Function<'a, T> {
+    Function::with_vjp(
+        TFN!(move |xs: &[T]| vec![xs[0]; k]),
+        TFN!(|_: &[T], gs: &[T]| vec![gs.iter().fold(T::default(), |s, g| s + *g)]),
+    )
+    .named("fan_out")
+}
+
+impl Op {
+    fn name(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Neg => "neg",
+            Op::Exp => "exp",
+            Op::Ln => "ln",
+            Op::Sin => "sin",
+            Op::Cos => "cos",
+            Op::Sqrt => "sqrt",
+        }
+    }
+    fn function<'a, T: Float>(self) -> Function<'a, T> {
+        match self {
+            Op::Add => binary::<T>(|a, b| a + b, |_, _, g| (g, g)),
+            Op::Sub => binary::<T>(|a, b| a - b, |_, _, g| (g, -g)),
+            Op::Mul => binary::<T>(|a, b| a * b, |a, b, g| (g * b, g * a)),
+            Op::Div => binary::<T>(|a, b| a / b, |a, b, g| (g / b, -(g * a) / (b * b))),
+            Op::Neg => unary::<T>(|x| -x, |_| -T::from_f64(1.0)),
+            Op::Exp => unary(T::exp, T::exp),
+            Op::Ln => unary(T::ln, |x| T::from_f64(1.0) / x),
+            Op::Sin => unary(T::sin, T::cos),
+            Op::Cos => unary(T::cos, |x| -x.sin()),
+            Op::Sqrt => unary(T::sqrt, |x| T::from_f64(0.5) / x.sqrt()),
+        }
+        .named(self.name())
+    }
+}
+
+enum Kind<T: Float> {
+    Input(T),
+    Constant(T),
+    Apply(Op),
+}
+
+struct Node<T: Float> {
+    kind: Kind<T>,
+    args: Vec<Traced<T>>,
+}
+
+/// take the arguments of nodes about to be dropped one by one, so that a long
+/// chain does not drop recursively
+impl<T: Float> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut args = std::mem::take(&mut self.args);
+        while let Some(Traced(node)) = args.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(node) {
+                args.append(&mut node.args);
+            }
+        }
+    }
+}
+
+/// a value inside a transformed closure
+#[derive(Clone)]
+pub struct Traced<T: Float>(Rc<Node<T>>);
+
+impl<T: Float> Traced<T> {
+    fn leaf(kind: Kind<T>) -> Self {
+        Traced(Rc::new(Node {
+            kind,
+            args: Vec::new(),
+        }))
+    }
+    fn apply(op: Op, args: Vec<Traced<T>>) -> Self {
+        Traced(Rc::new(Node {
+            kind: Kind::Apply(op),
+            args,
+        }))
+    }
+    fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+    /// a value no gradient is taken by
+    pub fn constant(x: T) -> Self {
+        Traced::leaf(Kind::Constant(x))
+    }
+    pub fn exp(&self) -> Self {
+        Traced::apply(Op::Exp, vec![self.clone()])
+    }
+    pub fn ln(&self) -> Self {
+        Traced::apply(Op::Ln, vec![self.clone()])
+    }
+    pub fn sin(&self) -> Self {
+        Traced::apply(Op::Sin, vec![self.clone()])
+    }
+    pub fn cos(&self) -> Self {
+        Traced::apply(Op::Cos, vec![self.clone()])
+    }
+    pub fn sqrt(&self) -> Self {
+        Traced::apply(Op::Sqrt, vec![self.clone()])
+    }
+}
+
+impl<T: Float> std::ops::Neg for Traced<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Traced::apply(Op::Neg, vec![self])
+    }
+}
+
+macro_rules! impl_traced_op {
+    ($trait: ident, $method: ident, $op: expr) => {
+        impl<T: Float> std::ops::$trait for Traced<T> {
+            type Output = Self;
+            fn $method(self, other: Self) -> Self {
+                Traced::apply($op, vec![self, other])
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<T> for Traced<T> {
+            type Output = Self;
+            fn $method(self, other: T) -> Self {
+                Traced::apply($op, vec![self, Traced::constant(other)])
+            }
+        }
+    };
+}
+
+impl_traced_op!(Add, add, Op::Add);
+impl_traced_op!(Sub, sub, Op::Sub);
+impl_traced_op!(Mul, mul, Op::Mul);
+impl_traced_op!(Div, div, Op::Div);
+
+/// the values `roots` depend on, each after its arguments, with the number
+/// of times each is used
+struct Trace<T: Float> {
+    nodes: Vec<Traced<T>>,
+    index: HashMap<usize, usize>,
+    uses: Vec<usize>,
+}
+
+impl<T: Float> Trace<T> {
+    fn new(roots: &[Traced<T>]) -> Self {
+        let mut trace = Trace {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            uses: Vec::new(),
+        };
+        for root in roots {
+            trace.visit(root);
+            trace.uses[trace.index[&root.id()]] += 1;
+        }
+        trace
+    }
+    /// add `root` and what it depends on, depth first with a stack of the
+    /// values to visit, each marked once its arguments are on top of it
+    fn visit(&mut self, root: &Traced<T>) {
+        let mut stack = vec![(root.clone(), false)];
+        while let Some((t, expanded)) = stack.pop() {
+            if self.index.contains_key(&t.id()) {
+                continue;
+            }
+            if !expanded {
+                stack.push((t.clone(), true));
+                stack.extend(t.0.args.iter().rev().map(|arg| (arg.clone(), false)));
+                continue;
+            }
+            for arg in &t.0.args {
+                self.uses[self.index[&arg.id()]] += 1;
+            }
+            self.index.insert(t.id(), self.nodes.len());
+            self.nodes.push(t);
+            self.uses.push(0);
+        }
+    }
+}
+
+/// the values of `roots` and, when asked, the gradients of their sum by
+/// `inputs`
+fn evaluate<T: Float>(
+    roots: &[Traced<T>],
+    inputs: &[Traced<T>],
+    differentiate: bool,
+) -> (Vec<T>, Vec<T>) {
+    let trace = Trace::new(roots);
+    // each node becomes a function, followed by a fan-out when it is used
+    // more than once; `ports` is where its uses connect
+    let mut functions = Vec::new();
+    let (mut mains, mut ports) = (Vec::new(), Vec::new());
+    for (node, uses) in trace.nodes.iter().zip(trace.uses.iter().copied()) {
+        mains.push(functions.len());
+        match node.0.kind {
+            Kind::Input(x) => functions.push(Function::coterminal(vec![x; uses])),
+            Kind::Constant(x) => {
+                functions.push(Function::coterminal(vec![x; uses]).with_requires_grad(false))
+            }
+            Kind::Apply(op) => {
+                functions.push(op.function());
+                if 1 < uses {
+                    functions.push(fan_out(uses));
+                }
+            }
+        }
+        ports.push(functions.len() - 1);
+    }
+    functions.push(Function::terminal(vec![T::from_f64(1.0); roots.len()]));
+    let port = |t: &Traced<T>| ports[trace.index[&t.id()]];
+    for (i, node) in trace.nodes.iter().enumerate() {
+        if ports[i] != mains[i] {
+            functions[mains[i]].link_to(&functions[ports[i]]);
+        }
+        for arg in &node.0.args {
+            functions[port(arg)].link_to(&functions[mains[i]]);
+        }
+    }
+    let terminal = &functions[functions.len() - 1];
+    for root in roots {
+        functions[port(root)].link_to(terminal);
+    }
+    for (i, node) in trace.nodes.iter().enumerate() {
+        if !matches!(node.0.kind, Kind::Apply(_)) {
+            functions[mains[i]].propagate_forward();
+        }
+    }
+    let values = terminal.on_f(|a| a.outputs());
+    if !differentiate {
+        return (values, Vec::new());
+    }
+    terminal.backward();
+    let grads = inputs
+        .iter()
+        .map(|x| match trace.index.get(&x.id()) {
+            Some(i) => functions[mains[*i]]
+                .on_b(|a| a.outputs())
+                .into_iter()
+                .fold(T::default(), |s, g| s + g),
+            None => T::default(),
+        })
+        .collect();
+    (values, grads)
+}
+
+/// `x -> (f(x), f'(x))`
+pub fn value_and_grad<T: Float>(f: impl Fn(Traced<T>) -> Traced<T>) -> impl Fn(T) -> (T, T) {
+    move |x| {
+        let x = Traced::leaf(Kind::Input(x));
+        let (values, grads) = evaluate(&[f(x.clone())], &[x], true);
+        (values[0], grads[0])
+    }
+}
+
+/// `x -> f'(x)`
+pub fn grad<T: Float>(f: impl Fn(Traced<T>) -> Traced<T>) -> impl Fn(T) -> T {
+    let f = value_and_grad(f);
+    move |x| f(x).1
+}
+
+/// `f` applied to every element of a batch, in a single graph
+pub fn vmap<T: Float>(f: impl Fn(Traced<T>) -> Traced<T>) -> impl Fn(&[T]) -> Vec<T> {
+    move |xs| {
+        let inputs = xs
+            .iter()
+            .map(|x| Traced::leaf(Kind::Input(*x)))
+            .collect::<Vec<_>>();
+        let roots = inputs.iter().cloned().map(&f).collect::<Vec<_>>();
+        evaluate(&roots, &inputs, false).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual::Dual;
+    #[test]
+    fn test_value_and_grad() {
+        // x sin(x) + exp(x) / 2, with x used three times
+        let f = value_and_grad(|x: Traced<f64>| x.clone() * x.sin() + x.exp() / 2.0);
+        let x = 0.3f64;
+        let (value, derivative) = f(x);
+        assert_eq!(value, x * x.sin() + x.exp() / 2.0);
+        assert!((derivative - (x.sin() + x * x.cos() + x.exp() / 2.0)).abs() < 1e-15);
+        let cube = grad(|x: Traced<f64>| x.clone() * x.clone() * x);
+        assert_eq!(cube(2.0), 12.0);
+        assert_eq!(cube(-1.0), 3.0);
+    }
+    #[test]
+    fn test_shared_intermediate() {
+        // s = sin(x) feeds both sides of s * s - ln(s)
+        let f = grad(|x: Traced<f64>| {
+            let s = x.sin();
+            s.clone() * s.clone() - s.ln()
+        });
+        let x = 1.1f64;
+        let exact = x.cos() * (2.0 * x.sin() - 1.0 / x.sin());
+        assert!((f(x) - exact).abs() < 1e-14);
+    }
+    #[test]
+    fn test_grad_of_constant() {
+        let f = value_and_grad(|_: Traced<f64>| Traced::constant(3.0));
+        assert_eq!(f(1.0), (3.0, 0.0));
+        let g = value_and_grad(|x: Traced<f64>| x);
+        assert_eq!(g(5.0), (5.0, 1.0));
+    }
+    #[test]
+    fn test_long_chain() {
+        // deep enough to overflow the stack if anything walked it recursively
+        let n = 20_000;
+        let f = value_and_grad(|x: Traced<f64>| (0..n).fold(x, |y, _| y.sin()));
+        let (value, derivative) = f(0.5);
+        let (mut y, mut d) = (0.5f64, 1.0);
+        for _ in 0..n {
+            d *= y.cos();
+            y = y.sin();
+        }
+        assert_eq!(value, y);
+        assert!((derivative - d).abs() < 1e-12 * d);
+    }
+    #[test]
+    fn test_vmap() {
+        let f = vmap(|x: Traced<f64>| (x.clone() * x).sqrt() + 1.0);
+        assert_eq!(f(&[-2.0, 0.0, 3.0]), vec![3.0, 1.0, 4.0]);
+        assert!(f(&[]).is_empty());
+    }
+    #[test]
+    fn test_second_derivative_through_duals() {
+        // the tangent of f'(x + ε) is f''(x)
+        let f = grad(|x: Traced<Dual<f64>>| x.cos() * x);
+        let x = 0.8f64;
+        let d = f(Dual::variable(x));
+        assert!((d.re - (x.cos() - x * x.sin())).abs() < 1e-15);
+        assert!((d.eps - (-2.0 * x.sin() - x * x.cos())).abs() < 1e-15);
+    }
+}
@@ -8,9 +8,119 @@ pub trait ContinuousDomain:
     + std::ops::Mul<Output = Self>
     + std::ops::Div<Output = Self>
 {
+    /// whether the value holds no NaN or infinity
+    fn is_finite(&self) -> bool {
+        true
+    }
+    /// a zero in the shape of the value
+    fn zeros_like(&self) -> Self {
+        Self::default()
+    }
 }
 
 impl ContinuousDomain for usize {}
 impl ContinuousDomain for u32 {}
-impl ContinuousDomain for f64 {}
-impl ContinuousDomain for f32 {}
+impl ContinuousDomain for f64 {
+    fn is_finite(&self) -> bool {
+        f64::is_finite(*self)
+    }
+}
+impl ContinuousDomain for f32 {
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+}
+
+/// domains with a one in the shape of every value, which seeds a backward
+/// pass by default
+pub trait OnesLike: ContinuousDomain {
+    fn ones_like(&self) -> Self;
+}
+
+impl<T: Float> OnesLike for T {
+    fn ones_like(&self) -> Self {
+        T::from_f64(1.0)
+    }
+}
+
+/// element types that can be ordered and summarized numerically
+pub trait Scalar: ContinuousDomain + PartialOrd + std::fmt::Display {
+    fn to_f64(&self) -> f64;
+}
+
+impl Scalar for usize {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+impl Scalar for u32 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+impl Scalar for f64 {
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+impl Scalar for f32 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+/// real floating point element types
+pub trait Float: Scalar + Copy + std::ops::Neg<Output = Self> {
+    fn from_f64(x: f64) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn hypot(self, other: Self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+    /// the least number greater than `self`
+    fn next_up(self) -> Self;
+    /// the greatest number less than `self`
+    fn next_down(self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($t: ty) => {
+        impl Float for $t {
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+            fn exp(self) -> Self {
+                <$t>::exp(self)
+            }
+            fn ln(self) -> Self {
+                <$t>::ln(self)
+            }
+            fn sin(self) -> Self {
+                <$t>::sin(self)
+            }
+            fn cos(self) -> Self {
+                <$t>::cos(self)
+            }
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+            fn hypot(self, other: Self) -> Self {
+                <$t>::hypot(self, other)
+            }
+            fn atan2(self, other: Self) -> Self {
+                <$t>::atan2(self, other)
+            }
+            fn next_up(self) -> Self {
+                <$t>::next_up(self)
+            }
+            fn next_down(self) -> Self {
+                <$t>::next_down(self)
+            }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);